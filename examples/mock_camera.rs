@@ -0,0 +1,18 @@
+//! The pretend PTZ camera of the integration tests on a fixed address, to
+//! try changes without hardware.
+//!
+//! cargo run --example mock_camera -- 127.0.0.1:8080
+//! cargo run -- --url http://127.0.0.1:8080 relative 0.1 0 0 --wait
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let camera = common::MockCamera::builder().log().bind(&addr).await?;
+    println!("mock camera listening on {}", camera.url);
+    std::future::pending().await
+}
@@ -0,0 +1,48 @@
+//! Hand a radar track over to a georeferenced PTZ camera.
+//!
+//! cargo run --example radar_handoff -- http://192.168.1.15 admin secret
+
+use test_ptz::{ptz, Device, DeviceError};
+use url::Url;
+
+/// A target as reported by the radar.
+struct Track {
+    id: u32,
+    lat: f64,
+    lon: f64,
+    /// Metres above sea level.
+    altitude: f32,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), DeviceError> {
+    let mut args = std::env::args().skip(1);
+    let url: Url = args
+        .next()
+        .ok_or(DeviceError::MissingUri)?
+        .parse()
+        .map_err(DeviceError::UrlParse)?;
+    let mut builder = Device::builder().uri(url);
+    if let (Some(user), Some(pass)) = (args.next(), args.next()) {
+        builder = builder.credentials(user, pass);
+    }
+    let device = builder.build().await?;
+
+    let track = Track {
+        id: 17,
+        lat: 52.379_189,
+        lon: 4.899_431,
+        altitude: 12.0,
+    };
+    println!("slewing to track {}", track.id);
+    // Frame a 20 x 20 m area around the target.
+    ptz::geo_move(
+        &device,
+        track.lat,
+        track.lon,
+        Some(track.altitude),
+        Some(20.0),
+        Some(20.0),
+    )
+    .await
+}
@@ -0,0 +1,113 @@
+use onvif::schema;
+use tracing::{debug, info};
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::media::get_profile_token;
+use crate::ptz::{get_ptz_node, PtzNodeInfo};
+
+/// Auxiliary commands with a standard ONVIF string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxCommand {
+    WiperOn,
+    WiperOff,
+    WasherOn,
+    WasherOff,
+    IrLampOn,
+    IrLampOff,
+    IrLampAuto,
+}
+
+impl AuxCommand {
+    /// The ONVIF spelling first, then ones seen on vendor firmware.
+    fn spellings(self) -> &'static [&'static str] {
+        match self {
+            Self::WiperOn => &["tt:Wiper|On", "Wiper|On", "tt:WiperOn", "WiperOn"],
+            Self::WiperOff => &["tt:Wiper|Off", "Wiper|Off", "tt:WiperOff", "WiperOff"],
+            Self::WasherOn => &["tt:Washer|On", "Washer|On", "tt:WasherOn", "WasherOn"],
+            Self::WasherOff => &["tt:Washer|Off", "Washer|Off", "tt:WasherOff", "WasherOff"],
+            Self::IrLampOn => &["tt:IRLamp|On", "IRLamp|On", "tt:IrLamp|On", "tt:IR|On"],
+            Self::IrLampOff => &["tt:IRLamp|Off", "IRLamp|Off", "tt:IrLamp|Off", "tt:IR|Off"],
+            Self::IrLampAuto => &[
+                "tt:IRLamp|Auto",
+                "IRLamp|Auto",
+                "tt:IrLamp|Auto",
+                "tt:IR|Auto",
+            ],
+        }
+    }
+
+    /// The spelling the camera lists, or the standard one if it lists none
+    /// that match.
+    fn pick(self, supported: &[String]) -> String {
+        self.spellings()
+            .iter()
+            .find_map(|spelling| {
+                supported
+                    .iter()
+                    .find(|command| command.eq_ignore_ascii_case(spelling))
+            })
+            .cloned()
+            .unwrap_or_else(|| self.spellings()[0].to_string())
+    }
+}
+
+/// Auxiliary commands the profile's PTZ node accepts.
+pub async fn list_auxiliary_commands(device: &Device) -> Result<Vec<String>, DeviceError> {
+    Ok(PtzNodeInfo::from(&get_ptz_node(device).await?).auxiliary_commands)
+}
+
+/// Send an auxiliary command such as a wiper or IR lamp switch and return
+/// the camera's response. The strings are vendor-specific, see
+/// `list_auxiliary_commands`.
+pub async fn send_auxiliary_command(device: &Device, command: &str) -> Result<String, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+
+    info!("auxiliary command: {}", command);
+    let response = schema::ptz::send_auxiliary_command(
+        ptz,
+        &schema::ptz::SendAuxiliaryCommand {
+            profile_token: get_profile_token(device).await?,
+            auxiliary_data: schema::onvif::AuxiliaryData(command.to_string()),
+        },
+    )
+    .await;
+    let response = device.track(response.map_err(DeviceError::from))?;
+    debug!("ptz auxiliary response: {:?}", response.auxiliary_response);
+
+    Ok(response.auxiliary_response.0)
+}
+
+/// Send `command` in the spelling the camera advertises.
+pub async fn send_aux_command(device: &Device, command: AuxCommand) -> Result<String, DeviceError> {
+    let supported = list_auxiliary_commands(device).await?;
+    send_auxiliary_command(device, &command.pick(&supported)).await
+}
+
+pub async fn wiper_on(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::WiperOn).await
+}
+
+pub async fn wiper_off(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::WiperOff).await
+}
+
+pub async fn washer_on(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::WasherOn).await
+}
+
+pub async fn washer_off(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::WasherOff).await
+}
+
+pub async fn ir_lamp_on(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::IrLampOn).await
+}
+
+pub async fn ir_lamp_off(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::IrLampOff).await
+}
+
+pub async fn ir_lamp_auto(device: &Device) -> Result<String, DeviceError> {
+    send_aux_command(device, AuxCommand::IrLampAuto).await
+}
@@ -0,0 +1,215 @@
+use onvif::schema;
+use serde::Serialize;
+use url::Url;
+
+use crate::device::{parse_xaddr, Device};
+use crate::error::DeviceError;
+use crate::ptz;
+use crate::trace::ServiceClient;
+
+/// What the PTZ service and the profile's PTZ node claim to support.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PtzCapabilities {
+    pub e_flip: bool,
+    pub reverse: bool,
+    pub move_status: bool,
+    pub status_position: bool,
+    pub continuous_move: bool,
+    pub absolute_move: bool,
+    pub relative_move: bool,
+    /// Relative moves of pan and tilt, not just zoom.
+    pub relative_pan_tilt: bool,
+    pub maximum_number_of_presets: i32,
+    pub home: bool,
+}
+
+/// Service-wide flags from the PTZ service's GetServiceCapabilities, as
+/// opposed to what a single node supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PtzServiceCapabilities {
+    pub e_flip: bool,
+    pub reverse: bool,
+    pub move_status: bool,
+    pub status_position: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MediaCapabilities {
+    pub snapshot_uri: bool,
+    pub rotation: bool,
+    /// On-screen display overlays, see `media::get_osds`.
+    pub osd: bool,
+    pub maximum_number_of_profiles: Option<i32>,
+}
+
+/// Services listed by GetCapabilities and their addresses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Capabilities {
+    pub media: Option<Url>,
+    pub ptz: Option<Url>,
+    pub imaging: Option<Url>,
+    pub events: Option<Url>,
+    pub analytics: Option<Url>,
+    /// From the PTZ service's GetServiceCapabilities, if it was fetched.
+    pub ptz_flags: Option<PtzCapabilities>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CachedCapabilities {
+    ptz: Option<PtzCapabilities>,
+    media: Option<MediaCapabilities>,
+}
+
+impl Device {
+    /// `None` until fetched, or if the camera has no PTZ service.
+    pub fn ptz_capabilities(&self) -> Option<PtzCapabilities> {
+        self.capabilities.read().unwrap().ptz
+    }
+
+    pub fn media_capabilities(&self) -> Option<MediaCapabilities> {
+        self.capabilities.read().unwrap().media
+    }
+
+    // Unknown capabilities count as supported so a failed fetch doesn't
+    // lock the helpers out.
+    pub fn supports_relative_move(&self) -> bool {
+        self.ptz_capabilities()
+            .map_or(true, |caps| caps.relative_move)
+    }
+
+    pub fn supports_relative_pan_tilt(&self) -> bool {
+        self.ptz_capabilities()
+            .map_or(true, |caps| caps.relative_pan_tilt)
+    }
+
+    pub fn supports_absolute_move(&self) -> bool {
+        self.ptz_capabilities()
+            .map_or(true, |caps| caps.absolute_move)
+    }
+
+    pub fn supports_continuous_move(&self) -> bool {
+        self.ptz_capabilities()
+            .map_or(true, |caps| caps.continuous_move)
+    }
+
+    pub fn supports_presets(&self) -> bool {
+        self.ptz_capabilities()
+            .map_or(true, |caps| caps.maximum_number_of_presets > 0)
+    }
+
+    pub fn supports_home(&self) -> bool {
+        self.ptz_capabilities().map_or(true, |caps| caps.home)
+    }
+
+    pub fn supports_osd(&self) -> bool {
+        self.media_capabilities().map_or(true, |caps| caps.osd)
+    }
+
+    /// Fetch GetServiceCapabilities for the PTZ and media services again,
+    /// e.g. after a firmware update.
+    pub async fn refresh_capabilities(&self) -> Result<(), DeviceError> {
+        // Fetched independently so a camera without media profiles, and so
+        // without a PTZ node, still reports its media capabilities.
+        let ptz = match self.ptz_client() {
+            Ok(client) => Some(fetch_ptz_capabilities(self, &client).await),
+            Err(_) => None,
+        };
+        let media = match self.media_client() {
+            Ok(client) => Some(fetch_media_capabilities(&client).await),
+            Err(_) => None,
+        };
+
+        let (ptz, ptz_error) = split(ptz);
+        let (media, media_error) = split(media);
+        *self.capabilities.write().unwrap() = CachedCapabilities { ptz, media };
+        match ptz_error.or(media_error) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn split<T>(result: Option<Result<T, DeviceError>>) -> (Option<T>, Option<DeviceError>) {
+    match result {
+        Some(Ok(value)) => (Some(value), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    }
+}
+
+pub async fn get_ptz_service_capabilities(
+    device: &Device,
+) -> Result<PtzServiceCapabilities, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    fetch_ptz_service_capabilities(ptz).await
+}
+
+async fn fetch_ptz_service_capabilities(
+    client: &ServiceClient,
+) -> Result<PtzServiceCapabilities, DeviceError> {
+    let service = schema::ptz::get_service_capabilities(client, &Default::default())
+        .await?
+        .capabilities;
+
+    Ok(PtzServiceCapabilities {
+        e_flip: service.e_flip.unwrap_or(false),
+        reverse: service.reverse.unwrap_or(false),
+        move_status: service.move_status.unwrap_or(false),
+        status_position: service.status_position.unwrap_or(false),
+    })
+}
+
+async fn fetch_ptz_capabilities(
+    device: &Device,
+    client: &ServiceClient,
+) -> Result<PtzCapabilities, DeviceError> {
+    let service = fetch_ptz_service_capabilities(client).await?;
+    let node = ptz::PtzNodeInfo::from(&ptz::get_ptz_node(device).await?);
+
+    Ok(PtzCapabilities {
+        e_flip: service.e_flip,
+        reverse: service.reverse,
+        move_status: service.move_status,
+        status_position: service.status_position,
+        continuous_move: node.supports_continuous_move(),
+        absolute_move: node.supports_absolute_move(),
+        relative_move: node.supports_relative_move(),
+        relative_pan_tilt: !node.spaces.relative_pan_tilt_translation.is_empty(),
+        maximum_number_of_presets: node.maximum_number_of_presets,
+        home: node.home_supported,
+    })
+}
+
+async fn fetch_media_capabilities(
+    client: &ServiceClient,
+) -> Result<MediaCapabilities, DeviceError> {
+    let service = schema::media::get_service_capabilities(client, &Default::default())
+        .await?
+        .capabilities;
+
+    Ok(MediaCapabilities {
+        snapshot_uri: service.snapshot_uri.unwrap_or(false),
+        rotation: service.rotation.unwrap_or(false),
+        osd: service.osd.unwrap_or(false),
+        maximum_number_of_profiles: service.profile_capabilities.maximum_number_of_profiles,
+    })
+}
+
+pub async fn get_capabilities(device: &Device) -> Result<Capabilities, DeviceError> {
+    let capabilities =
+        schema::devicemgmt::get_capabilities(&*device.device_mgmt(), &Default::default())
+            .await?
+            .capabilities;
+    let x_addr = |x_addr: Option<String>| -> Result<Option<Url>, DeviceError> {
+        x_addr.map(|x_addr| parse_xaddr(&x_addr)).transpose()
+    };
+
+    Ok(Capabilities {
+        media: x_addr(capabilities.media.map(|c| c.x_addr))?,
+        ptz: x_addr(capabilities.ptz.map(|c| c.x_addr))?,
+        imaging: x_addr(capabilities.imaging.map(|c| c.x_addr))?,
+        events: x_addr(capabilities.events.map(|c| c.x_addr))?,
+        analytics: x_addr(capabilities.analytics.map(|c| c.x_addr))?,
+        ptz_flags: device.ptz_capabilities(),
+    })
+}
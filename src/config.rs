@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::device::{AuthMode, Device, DeviceBuilder};
+use crate::error::DeviceError;
+use crate::ptz::{MoveStrategy, Orientation};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default, rename = "camera")]
+    pub cameras: Vec<CameraConfig>,
+    /// Models to recenter with relative moves, whatever they advertise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relative_models: Vec<String>,
+    /// Models that advertise relative moves but implement them badly.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub continuous_models: Vec<String>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraConfig {
+    pub name: String,
+    pub url: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthMode>,
+    /// Accept self-signed or otherwise invalid certificates.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+    /// Point advertised service addresses at `url`, for cameras behind NAT
+    /// or port forwarding.
+    #[serde(default)]
+    pub rewrite_xaddrs: bool,
+    /// Recenter with relative moves even if the camera doesn't advertise
+    /// them.
+    #[serde(default)]
+    pub prefer_relative: bool,
+    #[serde(default)]
+    pub invert_pan: bool,
+    #[serde(default)]
+    pub invert_tilt: bool,
+    #[serde(default)]
+    pub invert_zoom: bool,
+    #[serde(default)]
+    pub swap_pan_tilt: bool,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/test-ptz/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("test-ptz").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DeviceError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| DeviceError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&contents).map_err(|e| match e {
+            DeviceError::Config(msg) => DeviceError::Config(format!("{}: {}", path.display(), msg)),
+            e => e,
+        })
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, DeviceError> {
+        let config: Self =
+            toml::from_str(contents).map_err(|e| DeviceError::Config(e.to_string()))?;
+        config.warn_unknown_keys();
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, DeviceError> {
+        // Through a `Value`, which writes plain keys before tables. Written
+        // directly, an unknown key after the cameras is rejected.
+        let value = toml::Value::try_from(self).map_err(|e| DeviceError::Config(e.to_string()))?;
+        toml::to_string_pretty(&value).map_err(|e| DeviceError::Config(e.to_string()))
+    }
+
+    pub fn camera(&self, name: &str) -> Option<&CameraConfig> {
+        self.cameras.iter().find(|camera| camera.name == name)
+    }
+
+    /// `camera.builder()` with the model overrides of this file.
+    pub fn builder(&self, camera: &CameraConfig) -> DeviceBuilder {
+        let mut builder = camera.builder();
+        for model in &self.relative_models {
+            builder = builder.model_move_strategy(model, MoveStrategy::Relative);
+        }
+        for model in &self.continuous_models {
+            builder = builder.model_move_strategy(model, MoveStrategy::Continuous);
+        }
+        builder
+    }
+
+    fn warn_unknown_keys(&self) {
+        for key in self.unknown.keys() {
+            warn!("ignoring unknown config key {}", key);
+        }
+        for camera in &self.cameras {
+            for key in camera.unknown.keys() {
+                warn!("ignoring unknown key {} for camera {}", key, camera.name);
+            }
+        }
+    }
+}
+
+impl CameraConfig {
+    pub fn builder(&self) -> DeviceBuilder {
+        let mut builder = Device::builder()
+            .uri(self.url.clone())
+            .insecure_skip_verify(self.insecure_skip_verify)
+            .rewrite_xaddrs(self.rewrite_xaddrs)
+            .move_strategy(self.prefer_relative.then(|| MoveStrategy::Relative))
+            .orientation(Orientation {
+                invert_pan: self.invert_pan,
+                invert_tilt: self.invert_tilt,
+                invert_zoom: self.invert_zoom,
+                swap_pan_tilt: self.swap_pan_tilt,
+            });
+        if let Some(auth_mode) = self.auth {
+            builder = builder.auth_mode(auth_mode);
+        }
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(username.clone(), password.clone())
+            }
+            (None, None) => builder,
+            _ => {
+                warn!(
+                    "camera {} has only one of username and password, connecting unauthenticated",
+                    self.name
+                );
+                builder
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../config.example.toml");
+
+    #[test]
+    fn parses_every_camera_of_the_example() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        let names: Vec<_> = config.cameras.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["lobby", "loading-dock", "gate", "warehouse"]);
+        assert_eq!(config.continuous_models, ["IPD-E24Y00"]);
+
+        let lobby = config.camera("lobby").unwrap();
+        assert_eq!(lobby.url.as_str(), "http://192.168.1.15:888/");
+        assert_eq!(lobby.auth, Some(AuthMode::HttpDigest));
+        assert_eq!(lobby.username.as_deref(), Some("test"));
+
+        let dock = config.camera("loading-dock").unwrap();
+        assert!(dock.prefer_relative && dock.invert_tilt && !dock.invert_pan);
+        assert_eq!(dock.username, None);
+
+        assert!(config.camera("gate").unwrap().insecure_skip_verify);
+        assert!(config.camera("warehouse").unwrap().rewrite_xaddrs);
+        assert!(config.camera("attic").is_none());
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        let written = config.to_toml().unwrap();
+        assert_eq!(Config::parse(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn keeps_unknown_keys_when_written_back() {
+        let config = Config::parse(
+            r#"
+            relative_models = ["SD-1"]
+            theme = "dark"
+
+            [[camera]]
+            name = "a"
+            url = "http://10.0.0.1"
+            zoom_limit = 3
+
+            [[camera]]
+            name = "b"
+            url = "http://10.0.0.2"
+            auth = "basic"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.cameras.len(), 2);
+        assert_eq!(config.cameras[1].auth, Some(AuthMode::HttpBasic));
+
+        let written = config.to_toml().unwrap();
+        assert!(written.contains("theme = \"dark\""));
+        assert!(written.contains("zoom_limit = 3"));
+        assert_eq!(Config::parse(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn an_empty_file_has_no_cameras() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn reports_bad_files_as_config_errors() {
+        assert!(matches!(
+            Config::parse("[[camera]]\nname = \"no url\""),
+            Err(DeviceError::Config(_))
+        ));
+        assert!(matches!(
+            Config::parse("[[camera]]\nname = \"x\"\nurl = \"not a url\""),
+            Err(DeviceError::Config(_))
+        ));
+    }
+}
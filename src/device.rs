@@ -0,0 +1,1156 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use onvif::{
+    schema::{self, transport},
+    soap,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::capabilities::CachedCapabilities;
+use crate::error::{tls_failure, DeviceError};
+use crate::health::Health;
+use crate::ptz::{MoveProfile, MoveSpaces, MoveStrategy, Orientation};
+use crate::queue::CommandQueue;
+use crate::quirks::{Quirks, QuirksRegistry};
+use crate::retry::RetryPolicy;
+use crate::session::Motion;
+use crate::trace::{ServiceClient, TraceSink};
+
+/// Clients and settings found by service discovery. `Device::reconnect`
+/// swaps them as a whole, calls already under way finish on the old ones.
+#[derive(Clone)]
+pub(crate) struct Connection {
+    pub(crate) device_mgmt: ServiceClient,
+    pub(crate) media: Option<ServiceClient>,
+    pub(crate) ptz: Option<ServiceClient>,
+    pub(crate) imaging: Option<ServiceClient>,
+    pub(crate) events: Option<ServiceClient>,
+    pub(crate) media2: Option<ServiceClient>,
+    pub(crate) events_uri: Option<Url>,
+    pub(crate) auth_mode: AuthMode,
+    pub(crate) discovery_path: DiscoveryPath,
+    pub(crate) time_gap: Option<chrono::Duration>,
+}
+
+/// A service client of the connection current when it was looked up, e.g.
+/// by `Device::ptz_client`. Keeps working across a reconnect.
+#[derive(Clone)]
+pub struct Service {
+    connection: Arc<Connection>,
+    client: fn(&Connection) -> Option<&ServiceClient>,
+}
+
+impl Deref for Service {
+    type Target = ServiceClient;
+
+    fn deref(&self) -> &ServiceClient {
+        (self.client)(&self.connection).expect("checked by Device::service")
+    }
+}
+
+pub struct Device {
+    pub(crate) connection: RwLock<Arc<Connection>>,
+    /// Move spaces and ranges advertised per profile token.
+    pub(crate) move_spaces: Mutex<HashMap<String, MoveSpaces>>,
+    /// Whether relative moves are done as absolute ones per profile token,
+    /// decided by `ptz::send_relative_ptz` on first use.
+    pub(crate) emulate_relative: Mutex<HashMap<String, bool>>,
+    /// Token of the first media profile, fetched on first use.
+    pub(crate) profile_token: Mutex<Option<String>>,
+    pub(crate) info: Mutex<Option<DeviceInfo>>,
+    pub(crate) motion: Mutex<Motion>,
+    /// Runs PTZ moves one at a time.
+    pub(crate) ptz_queue: CommandQueue,
+    pub(crate) orientation: RwLock<Orientation>,
+    /// Picked by `ptz::move_strategy` on first use.
+    pub(crate) move_strategy: Mutex<Option<MoveStrategy>>,
+    /// Looked up by `quirks::quirks` on first use.
+    pub(crate) quirks: Mutex<Option<Quirks>>,
+    pub stop_timeout: Duration,
+    pub retry_policy: RetryPolicy,
+    pub(crate) config: DeviceBuilder,
+    pub(crate) health: Health,
+    pub(crate) capabilities: RwLock<CachedCapabilities>,
+}
+
+impl std::fmt::Debug for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let connection = self.connection();
+        f.debug_struct("Device")
+            .field("config", &self.config)
+            .field("auth_mode", &connection.auth_mode)
+            .field("discovery_path", &connection.discovery_path)
+            .field("media", &connection.media.is_some())
+            .field("ptz", &connection.ptz.is_some())
+            .field("media2", &connection.media2.is_some())
+            .field("events", &connection.events.is_some())
+            .field("imaging", &connection.imaging.is_some())
+            .field("stop_timeout", &self.stop_timeout)
+            .field("retry_policy", &self.retry_policy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub manufacturer: String,
+    pub model: String,
+    pub firmware_version: String,
+    pub serial_number: String,
+    pub hardware_id: String,
+}
+
+impl From<schema::devicemgmt::GetDeviceInformationResponse> for DeviceInfo {
+    fn from(response: schema::devicemgmt::GetDeviceInformationResponse) -> Self {
+        Self {
+            manufacturer: response.manufacturer,
+            model: response.model,
+            firmware_version: response.firmware_version,
+            serial_number: response.serial_number,
+            hardware_id: response.hardware_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMode {
+    /// Read-only calls go out unsigned first and are only signed if the
+    /// camera answers NotAuthorized, so stale credentials can't lock out an
+    /// open camera that counts failed attempts.
+    Auto,
+    /// Ignore any credentials and never sign requests.
+    Anonymous,
+    #[serde(alias = "username-token")]
+    WsUsernameToken,
+    #[serde(alias = "digest")]
+    HttpDigest,
+    #[serde(alias = "basic")]
+    HttpBasic,
+}
+
+impl Default for AuthMode {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl std::str::FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "username-token" | "ws-username-token" => Ok(Self::WsUsernameToken),
+            "digest" | "http-digest" => Ok(Self::HttpDigest),
+            "basic" | "http-basic" => Ok(Self::HttpBasic),
+            "anonymous" | "none" => Ok(Self::Anonymous),
+            _ => Err(format!(
+                "unknown auth mode {}, expected auto, anonymous, username-token, digest or basic",
+                s
+            )),
+        }
+    }
+}
+
+/// How the service addresses were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryPath {
+    GetServices,
+    /// The device faulted on GetServices, as some pre-2.0 devices do.
+    GetCapabilities,
+}
+
+const DEVICE_NAMESPACE: &str = "http://www.onvif.org/ver10/device/wsdl";
+fn service_name(namespace: &str) -> &'static str {
+    match namespace {
+        DEVICE_NAMESPACE => "device",
+        MEDIA_NAMESPACE => "media",
+        MEDIA2_NAMESPACE => "media2",
+        PTZ_NAMESPACE => "ptz",
+        IMAGING_NAMESPACE => "imaging",
+        EVENTS_NAMESPACE => "events",
+        _ => "other",
+    }
+}
+
+const MEDIA_NAMESPACE: &str = "http://www.onvif.org/ver10/media/wsdl";
+const PTZ_NAMESPACE: &str = "http://www.onvif.org/ver20/ptz/wsdl";
+const MEDIA2_NAMESPACE: &str = "http://www.onvif.org/ver20/media/wsdl";
+const EVENTS_NAMESPACE: &str = "http://www.onvif.org/ver10/events/wsdl";
+const IMAGING_NAMESPACE: &str = "http://www.onvif.org/ver20/imaging/wsdl";
+
+impl Device {
+    pub fn builder() -> DeviceBuilder {
+        DeviceBuilder::default()
+    }
+
+    /// Shorthand for the builder with a base URI and optional credentials.
+    /// Giving only one of `usr` and `pwd` is an `InvalidCredentials` error.
+    pub async fn new(
+        url: Option<Url>,
+        usr: Option<String>,
+        pwd: Option<String>,
+    ) -> Result<Self, DeviceError> {
+        let url = url.ok_or(DeviceError::MissingUri)?;
+        Self::from_device_service_url(url.join("onvif/device_service")?, usr, pwd).await
+    }
+
+    /// Connect to a device service at a non-standard path, e.g. an NVR
+    /// channel at `/onvif/device_service2`. The URL is used as is.
+    pub async fn from_device_service_url(
+        url: Url,
+        usr: Option<String>,
+        pwd: Option<String>,
+    ) -> Result<Self, DeviceError> {
+        let mut builder = Self::builder().device_service_uri(url);
+        match (usr, pwd) {
+            (Some(usr), Some(pwd)) => builder = builder.credentials(usr, pwd),
+            (None, None) => {}
+            _ => return Err(DeviceError::InvalidCredentials),
+        }
+
+        builder.build().await
+    }
+
+    pub(crate) fn connection(&self) -> Arc<Connection> {
+        self.connection.read().unwrap().clone()
+    }
+
+    pub(crate) fn service(
+        &self,
+        name: &'static str,
+        client: fn(&Connection) -> Option<&ServiceClient>,
+    ) -> Result<Service, DeviceError> {
+        let connection = self.connection();
+        client(&connection).ok_or(DeviceError::MissingService(name))?;
+        Ok(Service { connection, client })
+    }
+
+    pub fn device_mgmt(&self) -> Service {
+        Service {
+            connection: self.connection(),
+            client: |connection| Some(&connection.device_mgmt),
+        }
+    }
+
+    pub fn ptz_client(&self) -> Result<Service, DeviceError> {
+        self.service("ptz", |connection| connection.ptz.as_ref())
+    }
+
+    /// The authentication mechanism in use, as resolved by `AuthMode::Auto`.
+    pub fn auth_mode(&self) -> AuthMode {
+        self.connection().auth_mode
+    }
+
+    pub fn discovery_path(&self) -> DiscoveryPath {
+        self.connection().discovery_path
+    }
+
+    /// Make an authenticated call so a wrong password shows up as
+    /// `DeviceError::AuthenticationFailed` instead of deep inside another
+    /// request. Does nothing for anonymous devices.
+    pub async fn verify_credentials(&self) -> Result<(), DeviceError> {
+        let username = match self.credentials() {
+            Some(creds) => creds.username.clone(),
+            None => return Ok(()),
+        };
+
+        // Signed even in `AuthMode::Auto`: a camera with open reads answers
+        // the unsigned attempt whatever the password.
+        let device_mgmt = self.device_mgmt().signed();
+        match schema::devicemgmt::get_device_information(&device_mgmt, &Default::default()).await {
+            Ok(response) => {
+                // Saves `info` a round trip, e.g. for the relative blacklist.
+                *self.info.lock().unwrap() = Some(DeviceInfo::from(response));
+                Ok(())
+            }
+            Err(transport::Error::Authorization(_)) => {
+                Err(DeviceError::AuthenticationFailed { username })
+            }
+            Err(e) => match DeviceError::from(e) {
+                e if e.is_fault("NotAuthorized") => {
+                    Err(DeviceError::AuthenticationFailed { username })
+                }
+                e => Err(e),
+            },
+        }
+    }
+
+    /// GetDeviceInformation, fetched on first use and cached after.
+    pub async fn info(&self) -> Result<DeviceInfo, DeviceError> {
+        if let Some(ref info) = *self.info.lock().unwrap() {
+            return Ok(info.clone());
+        }
+
+        let response =
+            schema::devicemgmt::get_device_information(&*self.device_mgmt(), &Default::default())
+                .await?;
+        let info = DeviceInfo::from(response);
+        *self.info.lock().unwrap() = Some(info.clone());
+        Ok(info)
+    }
+
+    pub(crate) fn credentials(&self) -> Option<&soap::client::Credentials> {
+        match self.connection().auth_mode {
+            AuthMode::Anonymous => None,
+            _ => self.config.credentials.as_ref(),
+        }
+    }
+
+    /// Plain HTTP client honouring the TLS options, for requests outside of
+    /// SOAP like snapshot downloads.
+    pub(crate) fn http_client(&self) -> Result<reqwest::Client, DeviceError> {
+        Ok(self.config.http_client(AuthMode::Auto)?.unwrap_or_default())
+    }
+
+    /// A client for an address the camera handed out at runtime, such as a
+    /// pull point subscription, signed like the other services but with its
+    /// own `timeout`.
+    pub(crate) fn service_client(
+        &self,
+        x_addr: &str,
+        service: &'static str,
+        timeout: Duration,
+    ) -> Result<ServiceClient, DeviceError> {
+        let connection = self.connection();
+        let url = self
+            .config
+            .resolve_xaddr(x_addr, &origin(connection.device_mgmt.uri()))?;
+        let config = self.config.clone().timeout(timeout);
+        let http_client = config.http_client(connection.auth_mode)?;
+        Ok(config.soap_client(
+            &url,
+            service,
+            connection.auth_mode,
+            &http_client,
+            connection.time_gap,
+        ))
+    }
+
+    pub fn continuous_timeout(&self) -> Option<Duration> {
+        self.config.continuous_timeout
+    }
+
+    pub fn move_profile(&self) -> Option<MoveProfile> {
+        self.config.move_profile
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        *self.orientation.read().unwrap()
+    }
+
+    /// Override the configured orientation, e.g. after remounting.
+    pub fn set_orientation(&self, orientation: Orientation) {
+        *self.orientation.write().unwrap() = orientation;
+    }
+
+    pub(crate) fn strict_ranges(&self) -> bool {
+        self.config.strict_ranges
+    }
+
+    /// Upper bound for each request, past which it fails with
+    /// `DeviceError::Timeout`.
+    pub fn timeout(&self) -> Duration {
+        self.config.timeout
+    }
+
+    /// Re-measure the camera clock offset and apply it to every client. Call
+    /// this when a request fails with `DeviceError::is_auth_failure`.
+    pub async fn sync_clock(&self) -> Option<chrono::Duration> {
+        let mut connection = Connection::clone(&self.connection());
+        let time_gap = clock_offset(&connection.device_mgmt).await;
+        connection.time_gap = time_gap;
+        connection.device_mgmt.set_fix_time_gap(time_gap);
+        for client in [
+            &mut connection.media,
+            &mut connection.ptz,
+            &mut connection.media2,
+            &mut connection.events,
+            &mut connection.imaging,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            client.set_fix_time_gap(time_gap);
+        }
+        *self.connection.write().unwrap() = Arc::new(connection);
+        time_gap
+    }
+
+    /// Blocking variant of `new` for callers outside an async context. This
+    /// starts its own runtime, so it must not be called from within one.
+    pub fn new_blocking(
+        url: Option<Url>,
+        usr: Option<String>,
+        pwd: Option<String>,
+    ) -> Result<Self, DeviceError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| DeviceError::TaskFailed(e.to_string()))?;
+        runtime.block_on(Self::new(url, usr, pwd))
+    }
+}
+
+/// Connection settings for a `Device`. `build` resolves the services, e.g.
+/// `Device::builder().uri(uri).credentials(user, pass).build().await`.
+#[derive(Clone)]
+pub struct DeviceBuilder {
+    uri: Option<Url>,
+    device_service_uri: Option<Url>,
+    credentials: Option<soap::client::Credentials>,
+    rewrite_xaddrs: bool,
+    insecure_skip_verify: bool,
+    ca_certificate: Option<PathBuf>,
+    auth_mode: AuthMode,
+    timeout: Duration,
+    stop_timeout: Duration,
+    retry_policy: RetryPolicy,
+    verify_credentials: bool,
+    strict_ranges: bool,
+    continuous_timeout: Option<Duration>,
+    move_profile: Option<MoveProfile>,
+    orientation: Orientation,
+    move_strategy: Option<MoveStrategy>,
+    /// Upper-cased model names and the strategy forced for them.
+    model_move_strategies: HashMap<String, MoveStrategy>,
+    quirks_registry: Arc<QuirksRegistry>,
+    trace: Option<Arc<dyn TraceSink>>,
+}
+
+// Written by hand so the password never ends up in logs.
+impl std::fmt::Debug for DeviceBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceBuilder")
+            .field("uri", &self.uri)
+            .field("device_service_uri", &self.device_service_uri)
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|creds| &creds.username),
+            )
+            .field("password", &self.credentials.as_ref().map(|_| "<redacted>"))
+            .field("rewrite_xaddrs", &self.rewrite_xaddrs)
+            .field("insecure_skip_verify", &self.insecure_skip_verify)
+            .field("ca_certificate", &self.ca_certificate)
+            .field("auth_mode", &self.auth_mode)
+            .field("timeout", &self.timeout)
+            .field("stop_timeout", &self.stop_timeout)
+            .field("retry_policy", &self.retry_policy)
+            .field("verify_credentials", &self.verify_credentials)
+            .field("strict_ranges", &self.strict_ranges)
+            .field("continuous_timeout", &self.continuous_timeout)
+            .field("move_profile", &self.move_profile)
+            .field("orientation", &self.orientation)
+            .field("move_strategy", &self.move_strategy)
+            .field("model_move_strategies", &self.model_move_strategies)
+            .field("quirks_entries", &self.quirks_registry.entries().len())
+            .field("trace", &self.trace.is_some())
+            .finish()
+    }
+}
+
+impl Default for DeviceBuilder {
+    fn default() -> Self {
+        Self {
+            uri: None,
+            device_service_uri: None,
+            credentials: None,
+            rewrite_xaddrs: false,
+            insecure_skip_verify: false,
+            ca_certificate: None,
+            auth_mode: AuthMode::default(),
+            timeout: Duration::from_secs(5),
+            stop_timeout: Duration::from_secs(1),
+            retry_policy: RetryPolicy::default(),
+            verify_credentials: true,
+            strict_ranges: false,
+            continuous_timeout: Some(Duration::from_secs(5)),
+            move_profile: None,
+            orientation: Orientation::default(),
+            move_strategy: None,
+            model_move_strategies: HashMap::new(),
+            quirks_registry: Arc::new(QuirksRegistry::builtin()),
+            trace: None,
+        }
+    }
+}
+
+impl DeviceBuilder {
+    pub fn uri(mut self, uri: Url) -> Self {
+        self.uri = Some(uri);
+        self
+    }
+
+    /// Full URL of the device service, used instead of joining
+    /// `onvif/device_service` onto `uri`. Services are then expected under
+    /// its origin.
+    pub fn device_service_uri(mut self, uri: Url) -> Self {
+        self.device_service_uri = Some(uri);
+        self
+    }
+
+    pub fn credentials(mut self, username: String, password: String) -> Self {
+        self.credentials = Some(soap::client::Credentials { username, password });
+        self
+    }
+
+    /// Replace the scheme, host and port of every advertised service with the
+    /// base URI's instead of rejecting services outside of it. Useful for
+    /// cameras reached through NAT or port forwarding.
+    pub fn rewrite_xaddrs(mut self, rewrite_xaddrs: bool) -> Self {
+        self.rewrite_xaddrs = rewrite_xaddrs;
+        self
+    }
+
+    /// Accept any certificate the camera presents. Most cameras ship with a
+    /// self-signed certificate, prefer `ca_certificate` where possible.
+    pub fn insecure_skip_verify(mut self, insecure_skip_verify: bool) -> Self {
+        self.insecure_skip_verify = insecure_skip_verify;
+        self
+    }
+
+    /// Trust the PEM encoded CA certificate at `path` in addition to the
+    /// system roots.
+    pub fn ca_certificate(mut self, path: PathBuf) -> Self {
+        self.ca_certificate = Some(path);
+        self
+    }
+
+    /// How requests are authenticated. `AuthMode::Auto` probes the camera
+    /// with each mechanism in turn and keeps the first one it accepts.
+    pub fn auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    /// Upper bound for every SOAP request made through the device's clients.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Upper bound for PTZ stop requests, kept short so a hung stop is
+    /// noticed before the camera has slewed far.
+    pub fn stop_timeout(mut self, stop_timeout: Duration) -> Self {
+        self.stop_timeout = stop_timeout;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Check the credentials with an authenticated call before discovering
+    /// services, on by default. Anonymous devices are never checked.
+    pub fn verify_credentials(mut self, verify_credentials: bool) -> Self {
+        self.verify_credentials = verify_credentials;
+        self
+    }
+
+    /// How long continuous moves run unless stopped, 5 seconds by default.
+    /// `None` leaves it to the camera.
+    pub fn continuous_timeout(mut self, continuous_timeout: Option<Duration>) -> Self {
+        self.continuous_timeout = continuous_timeout;
+        self
+    }
+
+    /// Ramp the velocity of timed continuous moves, such as recentering,
+    /// instead of starting and stopping at full speed. Off by default.
+    pub fn move_profile(mut self, move_profile: Option<MoveProfile>) -> Self {
+        self.move_profile = move_profile;
+        self
+    }
+
+    /// How the camera is mounted, see `Device::set_orientation` to change it
+    /// later.
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Recenter this camera with `strategy` whatever it advertises.
+    pub fn move_strategy(mut self, strategy: Option<MoveStrategy>) -> Self {
+        self.move_strategy = strategy;
+        self
+    }
+
+    /// Recenter cameras of `model` with `strategy`, for models that
+    /// advertise relative moves but implement them badly.
+    pub fn model_move_strategy(mut self, model: &str, strategy: MoveStrategy) -> Self {
+        self.model_move_strategies
+            .insert(model.to_ascii_uppercase(), strategy);
+        self
+    }
+
+    /// Model quirks to look the camera up in, the built-in ones by default.
+    pub fn quirks(mut self, registry: QuirksRegistry) -> Self {
+        self.quirks_registry = Arc::new(registry);
+        self
+    }
+
+    /// Reject move values outside the advertised ranges instead of clamping
+    /// them, off by default.
+    pub fn strict_ranges(mut self, strict_ranges: bool) -> Self {
+        self.strict_ranges = strict_ranges;
+        self
+    }
+
+    /// Report the body of every SOAP request and response, with passwords
+    /// redacted, to `sink`.
+    pub fn trace_soap(mut self, sink: Arc<dyn TraceSink>) -> Self {
+        self.trace = Some(sink);
+        self
+    }
+
+    pub(crate) fn quirks_registry(&self) -> &QuirksRegistry {
+        &self.quirks_registry
+    }
+
+    pub(crate) fn forced_move_strategy(&self) -> Option<MoveStrategy> {
+        self.move_strategy
+    }
+
+    pub(crate) fn forced_model_move_strategy(&self, model: &str) -> Option<MoveStrategy> {
+        self.model_move_strategies
+            .get(&model.to_ascii_uppercase())
+            .copied()
+    }
+
+    fn http_client(&self, auth_mode: AuthMode) -> Result<Option<reqwest::Client>, DeviceError> {
+        let basic_auth = match (auth_mode, &self.credentials) {
+            (AuthMode::HttpBasic, Some(creds)) => Some(creds),
+            _ => None,
+        };
+
+        if !self.insecure_skip_verify && self.ca_certificate.is_none() && basic_auth.is_none() {
+            return Ok(None);
+        }
+
+        let mut builder =
+            reqwest::Client::builder().danger_accept_invalid_certs(self.insecure_skip_verify);
+        if let Some(ref path) = self.ca_certificate {
+            let pem = std::fs::read(path)
+                .map_err(|e| DeviceError::Tls(format!("{}: {}", path.display(), e)))?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| DeviceError::Tls(format!("{}: {}", path.display(), e)))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(creds) = basic_auth {
+            let token = base64::encode(format!("{}:{}", creds.username, creds.password));
+            let mut headers = reqwest::header::HeaderMap::new();
+            let value = reqwest::header::HeaderValue::from_str(&format!("Basic {}", token))
+                .map_err(|_| DeviceError::InvalidCredentials)?;
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+            builder = builder.default_headers(headers);
+        }
+
+        builder
+            .build()
+            .map(Some)
+            .map_err(|e| DeviceError::Tls(e.to_string()))
+    }
+
+    /// Timeout of the HTTP client underneath a `ServiceClient`, a little
+    /// longer than the `ServiceClient`'s own so that one fires first and
+    /// reports a `DeviceError::Timeout`.
+    fn soap_timeout(&self) -> Duration {
+        self.timeout + Duration::from_secs(1)
+    }
+
+    /// Try an HTTPS device service with a plain request and report a failed
+    /// handshake as `DeviceError::Tls`. The SOAP client only hands out errors
+    /// as text, so this is asked once the camera didn't answer.
+    async fn check_tls(&self, uri: &Url) -> Result<(), DeviceError> {
+        if uri.scheme() != "https" {
+            return Ok(());
+        }
+        let client = self.http_client(AuthMode::Auto)?.unwrap_or_default();
+        match client.get(uri.clone()).timeout(self.timeout).send().await {
+            Err(e) => match tls_failure(&e) {
+                Some(tls) => Err(DeviceError::Tls(tls.to_string())),
+                None => Ok(()),
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Parse an advertised address and check it against `base_uri`, or move
+    /// it onto `base_uri` if addresses are rewritten.
+    fn resolve_xaddr(&self, x_addr: &str, base_uri: &Url) -> Result<Url, DeviceError> {
+        let mut url = parse_xaddr(x_addr)?;
+        // Cameras behind a TLS terminator tend to advertise their plain
+        // http address, so follow the scheme the user connected with.
+        let scheme_differs = url.scheme() != base_uri.scheme() && url.host() == base_uri.host();
+        if self.rewrite_xaddrs || scheme_differs {
+            rewrite_origin(&mut url, base_uri)?;
+        } else if !is_within(&url, base_uri) {
+            return Err(DeviceError::ServiceUriMismatch {
+                advertised: x_addr.to_string(),
+                expected: base_uri.to_string(),
+            });
+        }
+        Ok(url)
+    }
+
+    fn soap_client(
+        &self,
+        uri: &Url,
+        service: &'static str,
+        auth_mode: AuthMode,
+        http_client: &Option<reqwest::Client>,
+        time_gap: Option<chrono::Duration>,
+    ) -> ServiceClient {
+        let client = self.signed_client(uri, service, auth_mode, http_client, time_gap);
+        self.anonymous_first(client, time_gap)
+    }
+
+    /// A client signing every request with `auth_mode`.
+    fn signed_client(
+        &self,
+        uri: &Url,
+        service: &'static str,
+        auth_mode: AuthMode,
+        http_client: &Option<reqwest::Client>,
+        time_gap: Option<chrono::Duration>,
+    ) -> ServiceClient {
+        let mut builder = soap::client::ClientBuilder::new(uri)
+            .timeout(self.soap_timeout())
+            .fix_time_gap(time_gap);
+        builder = match auth_mode {
+            AuthMode::Auto => builder.credentials(self.credentials.clone()),
+            AuthMode::WsUsernameToken => builder
+                .credentials(self.credentials.clone())
+                .auth_type(soap::client::AuthType::UsernameToken),
+            AuthMode::HttpDigest => builder
+                .credentials(self.credentials.clone())
+                .auth_type(soap::client::AuthType::Digest),
+            // The Authorization header is set on the HTTP client instead.
+            AuthMode::HttpBasic => builder,
+            AuthMode::Anonymous => builder,
+        };
+        if let Some(http_client) = http_client {
+            builder = builder.http_client(http_client.clone());
+        }
+        ServiceClient::new(
+            builder.build(),
+            service,
+            uri.clone(),
+            self.timeout,
+            self.trace.clone(),
+        )
+    }
+
+    /// In `AuthMode::Auto` with credentials, send the read-only calls of
+    /// `client` unsigned first.
+    fn anonymous_first(
+        &self,
+        client: ServiceClient,
+        time_gap: Option<chrono::Duration>,
+    ) -> ServiceClient {
+        if self.auth_mode != AuthMode::Auto || self.credentials.is_none() {
+            return client;
+        }
+        let mut anonymous = soap::client::ClientBuilder::new(client.uri())
+            .timeout(self.soap_timeout())
+            .fix_time_gap(time_gap);
+        // Any TLS error already surfaced when the signed client was built.
+        if let Ok(Some(http_client)) = self.http_client(AuthMode::Anonymous) {
+            anonymous = anonymous.http_client(http_client);
+        }
+        client.anonymous_first(anonymous.build())
+    }
+
+    pub async fn build(self) -> Result<Device, DeviceError> {
+        let (base_uri, device_mgmt_uri) = match (&self.device_service_uri, &self.uri) {
+            (Some(device_service_uri), _) => {
+                (origin(device_service_uri), device_service_uri.clone())
+            }
+            (None, Some(base_uri)) => (base_uri.clone(), base_uri.join("onvif/device_service")?),
+            (None, None) => return Err(DeviceError::MissingUri),
+        };
+
+        let candidates = match self.auth_mode {
+            AuthMode::Auto if self.credentials.is_some() => vec![
+                AuthMode::WsUsernameToken,
+                AuthMode::HttpDigest,
+                AuthMode::HttpBasic,
+            ],
+            auth_mode => vec![auth_mode],
+        };
+
+        // GetSystemDateAndTime doesn't require authentication, so the offset
+        // is known before the first signed request goes out.
+        let anonymous = {
+            let mut builder =
+                soap::client::ClientBuilder::new(&device_mgmt_uri).timeout(self.timeout);
+            if let Some(http_client) = self.http_client(AuthMode::Auto)? {
+                builder = builder.http_client(http_client);
+            }
+            builder.build()
+        };
+        let time_gap = clock_offset(&anonymous).await;
+        if time_gap.is_none() {
+            self.check_tls(&device_mgmt_uri).await?;
+        }
+
+        let mut selected = None;
+        for (i, &auth_mode) in candidates.iter().enumerate() {
+            let http_client = self.http_client(auth_mode)?;
+            let device_mgmt = self.signed_client(
+                &device_mgmt_uri,
+                "device",
+                auth_mode,
+                &http_client,
+                time_gap,
+            );
+            if i + 1 < candidates.len() {
+                // Probe with the signed client: an open camera would answer
+                // the anonymous fallback whatever the mode.
+                let probe =
+                    schema::devicemgmt::get_device_information(&*device_mgmt, &Default::default())
+                        .await;
+                if let Err(transport::Error::Authorization(_)) = probe {
+                    continue;
+                }
+            }
+            selected = Some((auth_mode, http_client, device_mgmt));
+            break;
+        }
+        let (auth_mode, http_client, device_mgmt) =
+            selected.expect("at least one auth mode is always tried");
+        let device_mgmt = self.anonymous_first(device_mgmt, time_gap);
+
+        let mut connection = Connection {
+            device_mgmt,
+            media: None,
+            ptz: None,
+            media2: None,
+            events: None,
+            events_uri: None,
+            imaging: None,
+            auth_mode,
+            discovery_path: DiscoveryPath::GetServices,
+            time_gap,
+        };
+        let mut out = Device {
+            connection: RwLock::new(Arc::new(connection.clone())),
+            move_spaces: Default::default(),
+            emulate_relative: Default::default(),
+            profile_token: Default::default(),
+            info: Default::default(),
+            motion: Default::default(),
+            ptz_queue: Default::default(),
+            orientation: RwLock::new(self.orientation),
+            move_strategy: Default::default(),
+            quirks: Default::default(),
+            stop_timeout: self.stop_timeout,
+            retry_policy: self.retry_policy.clone(),
+            config: self.clone(),
+            health: Health::default(),
+            capabilities: Default::default(),
+        };
+
+        if self.verify_credentials {
+            out.verify_credentials().await?;
+        }
+
+        let services: Vec<(String, String)> =
+            match schema::devicemgmt::get_services(&connection.device_mgmt, &Default::default())
+                .await
+            {
+                Ok(services) if !services.service.is_empty() => services
+                    .service
+                    .into_iter()
+                    .map(|s| (s.namespace, s.x_addr))
+                    .collect(),
+                // Some firmware answers GetServices with an empty list rather
+                // than a fault.
+                Ok(_) => {
+                    warn!("GetServices returned no services, falling back to GetCapabilities");
+                    connection.discovery_path = DiscoveryPath::GetCapabilities;
+                    services_from_capabilities(&connection.device_mgmt)
+                        .await
+                        .unwrap_or_default()
+                }
+                Err(e @ transport::Error::Authorization(_)) => {
+                    return Err(DeviceError::service_discovery(e))
+                }
+                Err(e) => {
+                    warn!(
+                        "GetServices failed ({}), falling back to GetCapabilities",
+                        e
+                    );
+                    connection.discovery_path = DiscoveryPath::GetCapabilities;
+                    match services_from_capabilities(&connection.device_mgmt).await {
+                        Some(services) => services,
+                        None => return Err(DeviceError::service_discovery(e)),
+                    }
+                }
+            };
+
+        for (namespace, x_addr) in &services {
+            let url = self.resolve_xaddr(x_addr, &base_uri)?;
+
+            let svc = Some(self.soap_client(
+                &url,
+                service_name(namespace),
+                auth_mode,
+                &http_client,
+                time_gap,
+            ));
+
+            match namespace.as_str() {
+                DEVICE_NAMESPACE => {
+                    if !same_endpoint(&url, &device_mgmt_uri) {
+                        return Err(DeviceError::ServiceUriMismatch {
+                            advertised: x_addr.clone(),
+                            expected: device_mgmt_uri.to_string(),
+                        });
+                    }
+                }
+                MEDIA_NAMESPACE => connection.media = svc,
+                PTZ_NAMESPACE => connection.ptz = svc,
+                MEDIA2_NAMESPACE => connection.media2 = svc,
+                EVENTS_NAMESPACE => {
+                    connection.events = svc;
+                    connection.events_uri = Some(url.clone());
+                }
+                IMAGING_NAMESPACE => connection.imaging = svc,
+                _ => {}
+            }
+        }
+
+        *out.connection.get_mut().unwrap() = Arc::new(connection);
+        if let Err(e) = out.refresh_capabilities().await {
+            warn!("failed to fetch service capabilities: {}", e);
+        }
+
+        Ok(out)
+    }
+}
+
+/// Service addresses from the older GetCapabilities call, as
+/// `(namespace, x_addr)` pairs like GetServices returns.
+async fn services_from_capabilities(
+    device_mgmt: &impl transport::Transport,
+) -> Option<Vec<(String, String)>> {
+    let capabilities = schema::devicemgmt::get_capabilities(device_mgmt, &Default::default())
+        .await
+        .ok()?
+        .capabilities;
+
+    let mut services = vec![];
+    if let Some(device) = capabilities.device {
+        services.push((DEVICE_NAMESPACE.to_string(), device.x_addr));
+    }
+    if let Some(media) = capabilities.media {
+        services.push((MEDIA_NAMESPACE.to_string(), media.x_addr));
+    }
+    if let Some(ptz) = capabilities.ptz {
+        services.push((PTZ_NAMESPACE.to_string(), ptz.x_addr));
+    }
+    if let Some(events) = capabilities.events {
+        services.push((EVENTS_NAMESPACE.to_string(), events.x_addr));
+    }
+    if let Some(imaging) = capabilities.imaging {
+        services.push((IMAGING_NAMESPACE.to_string(), imaging.x_addr));
+    }
+    Some(services)
+}
+
+async fn clock_offset(device_mgmt: &impl transport::Transport) -> Option<chrono::Duration> {
+    let utc = schema::devicemgmt::get_system_date_and_time(device_mgmt, &Default::default())
+        .await
+        .ok()?
+        .system_date_and_time
+        .utc_date_time?;
+
+    let camera_time =
+        NaiveDate::from_ymd_opt(utc.date.year, utc.date.month as u32, utc.date.day as u32)?
+            .and_hms_opt(
+                utc.time.hour as u32,
+                utc.time.minute as u32,
+                utc.time.second as u32,
+            )?;
+
+    Some(camera_time - Utc::now().naive_utc())
+}
+
+/// Parse an advertised XAddr. Some cameras append an IPv6 zone id, which
+/// `Url` rejects and which is meaningless off-link anyway, so drop it.
+pub(crate) fn parse_xaddr(x_addr: &str) -> Result<Url, DeviceError> {
+    let x_addr = x_addr.trim();
+    match (x_addr.find('['), x_addr.find('%'), x_addr.find(']')) {
+        (Some(open), Some(zone), Some(close)) if open < zone && zone < close => {
+            let stripped = format!("{}{}", &x_addr[..zone], &x_addr[close..]);
+            Ok(Url::parse(&stripped)?)
+        }
+        _ => Ok(Url::parse(x_addr)?),
+    }
+}
+
+/// Compare scheme, host and port structurally so IPv6 literals, elided
+/// default ports and trailing slashes don't matter.
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme()
+        && a.host() == b.host()
+        && a.port_or_known_default() == b.port_or_known_default()
+}
+
+/// Whether `url` is on the same origin as `base` and below its path.
+fn is_within(url: &Url, base: &Url) -> bool {
+    let base_path = base.path().trim_end_matches('/');
+    let path = url.path();
+    same_origin(url, base)
+        && path.starts_with(base_path)
+        && matches!(path[base_path.len()..].chars().next(), None | Some('/'))
+}
+
+fn same_endpoint(a: &Url, b: &Url) -> bool {
+    same_origin(a, b) && a.path().trim_end_matches('/') == b.path().trim_end_matches('/')
+}
+
+fn origin(url: &Url) -> Url {
+    let mut origin = url.clone();
+    origin.set_path("/");
+    origin.set_query(None);
+    origin.set_fragment(None);
+    origin
+}
+
+fn rewrite_origin(url: &mut Url, base_uri: &Url) -> Result<(), DeviceError> {
+    let advertised = url.to_string();
+    let rewrite_failed = || DeviceError::ServiceUriMismatch {
+        advertised: advertised.clone(),
+        expected: base_uri.to_string(),
+    };
+
+    url.set_scheme(base_uri.scheme())
+        .map_err(|_| rewrite_failed())?;
+    url.set_host(base_uri.host_str())
+        .map_err(|_| rewrite_failed())?;
+    url.set_port(base_uri.port())
+        .map_err(|_| rewrite_failed())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parse_xaddr_drops_the_ipv6_zone() {
+        let parsed = parse_xaddr(" http://[fe80::1%eth0]:8080/onvif/ptz_service\n").unwrap();
+        assert_eq!(parsed.as_str(), "http://[fe80::1]:8080/onvif/ptz_service");
+        assert!(parse_xaddr("not a url").is_err());
+    }
+
+    #[test]
+    fn ipv6_literals_compare_structurally() {
+        assert!(same_endpoint(
+            &url("http://[fe80::1]/onvif/device_service"),
+            &url("http://[FE80:0:0:0:0:0:0:1]:80/onvif/device_service"),
+        ));
+        assert!(!same_endpoint(
+            &url("http://[fe80::1]/onvif/device_service"),
+            &url("http://[fe80::2]/onvif/device_service"),
+        ));
+    }
+
+    #[test]
+    fn default_ports_and_trailing_slashes_match() {
+        assert!(same_endpoint(
+            &url("http://camera/onvif/device_service"),
+            &url("http://camera:80/onvif/device_service/"),
+        ));
+        assert!(same_endpoint(
+            &url("https://camera:443/onvif/device_service"),
+            &url("https://camera/onvif/device_service"),
+        ));
+        assert!(!same_endpoint(
+            &url("http://camera/onvif/device_service"),
+            &url("http://camera:8080/onvif/device_service"),
+        ));
+        assert!(!same_endpoint(
+            &url("http://camera/onvif/device_service"),
+            &url("https://camera/onvif/device_service"),
+        ));
+    }
+
+    #[test]
+    fn is_within_respects_path_segments() {
+        let base = url("http://camera/onvif/");
+        assert!(is_within(&url("http://camera:80/onvif/ptz_service"), &base));
+        assert!(is_within(&url("http://camera/onvif"), &base));
+        assert!(!is_within(&url("http://camera/onvif2/ptz_service"), &base));
+        assert!(!is_within(&url("http://other/onvif/ptz_service"), &base));
+        assert!(is_within(
+            &url("http://camera/anything"),
+            &url("http://camera")
+        ));
+    }
+
+    #[test]
+    fn origin_keeps_only_scheme_host_and_port() {
+        let origin = origin(&url("https://[::1]:8443/onvif/device_service?x=1#y"));
+        assert_eq!(origin.as_str(), "https://[::1]:8443/");
+    }
+
+    #[test]
+    fn rewrite_moves_the_path_onto_the_base() {
+        let mut advertised = url("http://192.168.0.10:80/onvif/ptz_service");
+        rewrite_origin(&mut advertised, &url("https://203.0.113.7:8443/")).unwrap();
+        assert_eq!(
+            advertised.as_str(),
+            "https://203.0.113.7:8443/onvif/ptz_service"
+        );
+
+        let mut advertised = url("http://192.168.0.10:8080/onvif/ptz_service");
+        rewrite_origin(&mut advertised, &url("http://gate.example.net/")).unwrap();
+        assert_eq!(
+            advertised.as_str(),
+            "http://gate.example.net/onvif/ptz_service"
+        );
+    }
+
+    #[test]
+    fn resolve_xaddr_checks_or_rewrites() {
+        let base = url("https://camera/");
+        let strict = Device::builder();
+        assert!(matches!(
+            strict.resolve_xaddr("https://10.0.0.9/onvif/ptz_service", &base),
+            Err(DeviceError::ServiceUriMismatch { .. })
+        ));
+        // Same host over plain http, as from behind a TLS terminator.
+        assert_eq!(
+            strict
+                .resolve_xaddr("http://camera/onvif/ptz_service", &base)
+                .unwrap()
+                .as_str(),
+            "https://camera/onvif/ptz_service"
+        );
+
+        let rewriting = Device::builder().rewrite_xaddrs(true);
+        assert_eq!(
+            rewriting
+                .resolve_xaddr("http://10.0.0.9:80/onvif/ptz_service", &base)
+                .unwrap()
+                .as_str(),
+            "https://camera/onvif/ptz_service"
+        );
+    }
+}
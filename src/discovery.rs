@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use url::Url;
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const MULTICAST_PORT: u16 = 3702;
+
+const PROBE_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"
+            xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing"
+            xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery"
+            xmlns:dn="http://www.onvif.org/ver10/network/wsdl">
+  <s:Header>
+    <a:Action s:mustUnderstand="1">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</a:Action>
+    <a:MessageID>uuid:{message_id}</a:MessageID>
+    <a:ReplyTo>
+      <a:Address>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:Address>
+    </a:ReplyTo>
+    <a:To s:mustUnderstand="1">urn:schemas-xmlsoap-org:ws:2005:04:discovery</a:To>
+  </s:Header>
+  <s:Body>
+    <d:Probe>
+      <d:Types>dn:NetworkVideoTransmitter</d:Types>
+    </d:Probe>
+  </s:Body>
+</s:Envelope>"#;
+
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    pub endpoint_reference: String,
+    pub types: Vec<String>,
+    pub scopes: Vec<String>,
+    pub x_addrs: Vec<Url>,
+}
+
+impl DiscoveredDevice {
+    pub fn is_network_video_transmitter(&self) -> bool {
+        self.types
+            .iter()
+            .any(|t| t.ends_with("NetworkVideoTransmitter"))
+    }
+}
+
+/// Sends a WS-Discovery probe out of every IPv4 interface and collects the
+/// matches that arrive before `timeout`, keyed by endpoint reference.
+pub async fn discover(timeout: Duration) -> Result<Vec<DiscoveredDevice>, String> {
+    let deadline = Instant::now() + timeout;
+    let probe = PROBE_TEMPLATE.replace("{message_id}", &uuid::Uuid::new_v4().to_string());
+
+    let mut listeners = vec![];
+    let mut last_error = None;
+    for interface in local_ipv4_addrs()? {
+        match probe_interface(interface, &probe).await {
+            Ok(socket) => listeners.push(tokio::spawn(collect_matches(socket, deadline))),
+            Err(e) => last_error = Some(format!("{}: {}", interface, e)),
+        }
+    }
+
+    if listeners.is_empty() {
+        return Err(last_error.unwrap_or_else(|| "no IPv4 interface to probe from".to_string()));
+    }
+
+    let mut seen = HashSet::new();
+    let mut devices = vec![];
+    for listener in listeners {
+        for device in listener.await.map_err(|e| e.to_string())? {
+            if seen.insert(device.endpoint_reference.clone()) {
+                devices.push(device);
+            }
+        }
+    }
+
+    Ok(devices)
+}
+
+fn local_ipv4_addrs() -> Result<Vec<Ipv4Addr>, String> {
+    let addrs = if_addrs::get_if_addrs()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|interface| !interface.is_loopback())
+        .filter_map(|interface| match interface.ip() {
+            IpAddr::V4(addr) => Some(addr),
+            IpAddr::V6(_) => None,
+        })
+        .collect();
+
+    Ok(addrs)
+}
+
+async fn probe_interface(interface: Ipv4Addr, probe: &str) -> Result<UdpSocket, String> {
+    let socket =
+        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(|e| e.to_string())?;
+    socket
+        .set_multicast_if_v4(&interface)
+        .map_err(|e| e.to_string())?;
+    socket
+        .bind(&SocketAddr::from((interface, 0)).into())
+        .map_err(|e| e.to_string())?;
+    socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    let socket = UdpSocket::from_std(socket.into()).map_err(|e| e.to_string())?;
+    socket
+        .send_to(probe.as_bytes(), (MULTICAST_ADDR, MULTICAST_PORT))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(socket)
+}
+
+async fn collect_matches(socket: UdpSocket, deadline: Instant) -> Vec<DiscoveredDevice> {
+    let mut devices = vec![];
+    let mut buf = vec![0u8; 65535];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        match tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await {
+            Ok(Ok((len, _))) => devices.extend(parse_probe_matches(&buf[..len])),
+            Ok(Err(_)) | Err(_) => break,
+        }
+    }
+
+    devices
+}
+
+fn parse_probe_matches(payload: &[u8]) -> Vec<DiscoveredDevice> {
+    let envelope = match xmltree::Element::parse(payload) {
+        Ok(envelope) => envelope,
+        Err(_) => return vec![],
+    };
+
+    let matches = match envelope
+        .get_child("Body")
+        .and_then(|body| body.get_child("ProbeMatches"))
+    {
+        Some(matches) => matches,
+        None => return vec![],
+    };
+
+    matches
+        .children
+        .iter()
+        .filter_map(|node| node.as_element())
+        .filter(|element| element.name == "ProbeMatch")
+        .filter_map(|probe_match| {
+            let endpoint_reference = probe_match
+                .get_child("EndpointReference")
+                .and_then(|epr| epr.get_child("Address"))
+                .and_then(|address| address.get_text())?
+                .trim()
+                .to_string();
+
+            Some(DiscoveredDevice {
+                endpoint_reference,
+                types: child_list(probe_match, "Types"),
+                scopes: child_list(probe_match, "Scopes"),
+                x_addrs: child_list(probe_match, "XAddrs")
+                    .iter()
+                    .filter_map(|x_addr| Url::parse(x_addr).ok())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+fn child_list(element: &xmltree::Element, name: &str) -> Vec<String> {
+    element
+        .get_child(name)
+        .and_then(|child| child.get_text())
+        .map(|text| text.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,145 @@
+use onvif::schema;
+use tracing::info;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+
+/// Encoder settings to change with `set_video_encoder`, `None` keeps the
+/// current value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SetEncoderParams {
+    /// Width and height in pixels.
+    pub resolution: Option<(i32, i32)>,
+    pub bitrate_kbps: Option<i32>,
+    pub framerate: Option<i32>,
+    /// Frames between key frames, for H.264 and MPEG-4 only.
+    pub gop: Option<i32>,
+}
+
+pub async fn get_video_encoder_configs(
+    device: &Device,
+) -> Result<Vec<schema::onvif::VideoEncoderConfiguration>, DeviceError> {
+    let media_client = &*device.media_client()?;
+    Ok(device
+        .retry_policy
+        .run("get_video_encoder_configurations", true, || async move {
+            schema::media::get_video_encoder_configurations(media_client, &Default::default())
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .configurations)
+}
+
+/// Resolutions the camera offers for the encoding `config` uses.
+fn available_resolutions(
+    config: &schema::onvif::VideoEncoderConfiguration,
+    options: &schema::onvif::VideoEncoderConfigurationOptions,
+) -> Vec<(i32, i32)> {
+    let resolutions = match config.encoding {
+        schema::onvif::VideoEncoding::Jpeg => {
+            options.jpeg.as_ref().map(|o| &o.resolutions_available)
+        }
+        schema::onvif::VideoEncoding::Mpeg4 => {
+            options.mpeg4.as_ref().map(|o| &o.resolutions_available)
+        }
+        schema::onvif::VideoEncoding::H264 => {
+            options.h264.as_ref().map(|o| &o.resolutions_available)
+        }
+        _ => None,
+    };
+    resolutions
+        .map(|resolutions| {
+            resolutions
+                .iter()
+                .map(|resolution| (resolution.width, resolution.height))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Change the encoder configuration `token`. The current configuration is
+/// fetched, the given fields replaced and the result saved across reboots.
+/// Resolutions the camera doesn't offer are rejected before sending, as are
+/// a bitrate or frame rate for a configuration without rate control.
+pub async fn set_video_encoder(
+    device: &Device,
+    token: &str,
+    params: SetEncoderParams,
+) -> Result<(), DeviceError> {
+    let media_client = &*device.media_client()?;
+    let configuration_token = schema::onvif::ReferenceToken(token.to_string());
+
+    let mut configuration = schema::media::get_video_encoder_configuration(
+        media_client,
+        &schema::media::GetVideoEncoderConfiguration {
+            configuration_token: configuration_token.clone(),
+        },
+    )
+    .await?
+    .configuration;
+
+    if let Some((width, height)) = params.resolution {
+        let options = schema::media::get_video_encoder_configuration_options(
+            media_client,
+            &schema::media::GetVideoEncoderConfigurationOptions {
+                configuration_token: Some(configuration_token),
+                profile_token: None,
+            },
+        )
+        .await?
+        .options;
+        let available = available_resolutions(&configuration, &options);
+        if !available.contains(&(width, height)) {
+            let available: Vec<String> = available
+                .iter()
+                .map(|(width, height)| format!("{}x{}", width, height))
+                .collect();
+            return Err(DeviceError::InvalidArgument(format!(
+                "resolution {}x{} is not offered, available: {}",
+                width,
+                height,
+                available.join(", ")
+            )));
+        }
+        configuration.resolution = schema::onvif::VideoResolution { width, height };
+    }
+
+    if params.bitrate_kbps.is_some() || params.framerate.is_some() {
+        // A rate control made up here would send zero for the frame rate
+        // and encoding interval that weren't asked for.
+        let rate_control = configuration
+            .rate_control
+            .as_mut()
+            .ok_or(DeviceError::Unsupported(
+                "bitrate or frame rate of an encoder without rate control",
+            ))?;
+        if let Some(bitrate_kbps) = params.bitrate_kbps {
+            rate_control.bitrate_limit = bitrate_kbps;
+        }
+        if let Some(framerate) = params.framerate {
+            rate_control.frame_rate_limit = framerate;
+        }
+    }
+
+    if let Some(gop) = params.gop {
+        match (configuration.h264.as_mut(), configuration.mpeg4.as_mut()) {
+            (Some(h264), _) => h264.gov_length = gop,
+            (None, Some(mpeg4)) => mpeg4.gov_length = gop,
+            (None, None) => return Err(DeviceError::Unsupported("GOP length for this encoding")),
+        }
+    }
+
+    info!("set video encoder {}: {:?}", token, params);
+    let response = schema::media::set_video_encoder_configuration(
+        media_client,
+        &schema::media::SetVideoEncoderConfiguration {
+            configuration,
+            force_persistence: true,
+        },
+    )
+    .await;
+    device.track(response.map_err(DeviceError::from))?;
+
+    Ok(())
+}
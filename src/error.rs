@@ -0,0 +1,363 @@
+use std::fmt;
+
+use onvif::schema::transport;
+
+use crate::ptz::PtzStatus;
+use crate::trace::TIMED_OUT;
+
+#[derive(Debug)]
+pub enum DeviceError {
+    InvalidCredentials,
+    /// The camera rejected the credentials, retrying won't help.
+    AuthenticationFailed {
+        username: String,
+    },
+    MissingUri,
+    Config(String),
+    UrlParse(url::ParseError),
+    ServiceDiscovery {
+        source: transport::Error,
+    },
+    ServiceUriMismatch {
+        advertised: String,
+        expected: String,
+    },
+    MissingService(&'static str),
+    NoPtzNode,
+    NoVideoSource,
+    /// The camera has no media profiles to move or stream.
+    NoProfiles,
+    UnknownProfile {
+        requested: String,
+        available: Vec<String>,
+    },
+    Unsupported(&'static str),
+    InvalidArgument(String),
+    TooManyPresets,
+    /// GotoHomePosition before a home position was set, with the fault text.
+    NoHomePosition(String),
+    /// The camera clock is set by NTP and can't be set manually.
+    NtpEnabled,
+    /// `wait_for_idle` ran out of time, with the last status it saw.
+    StillMoving(Box<PtzStatus>),
+    /// The TLS handshake failed, e.g. on an untrusted certificate.
+    Tls(String),
+    Timeout,
+    UnknownDevice(String),
+    TaskFailed(String),
+    /// The request didn't get an answer, e.g. the connection dropped.
+    Transport(transport::Error),
+    /// The camera answered with a SOAP fault. Retrying won't help.
+    Fault(SoapFault),
+    /// A request couldn't be written or a response couldn't be read.
+    Encoding(transport::Error),
+    Http(String),
+}
+
+/// A SOAP fault as the transport reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoapFault {
+    /// Code and subcode values without their namespace prefix, e.g.
+    /// `Sender`, `InvalidArgVal`, `NoProfile`.
+    pub codes: Vec<String>,
+    pub message: String,
+}
+
+impl SoapFault {
+    /// Picks the code values out of the fault, either the `Value` elements
+    /// of a fault body or the qualified names of a formatted one.
+    fn parse(message: String) -> Self {
+        let codes = fault_body(&message)
+            .map(|body| {
+                let mut codes = vec![];
+                collect_code_values(&body, &mut codes);
+                codes
+            })
+            .unwrap_or_else(|| qualified_names(&message));
+        Self { codes, message }
+    }
+
+    /// Whether `code` is the fault's code or one of its subcodes, e.g.
+    /// `fault.has_code("TooManyPresets")`.
+    pub fn has_code(&self, code: &str) -> bool {
+        self.codes.iter().any(|c| c == code)
+    }
+}
+
+fn fault_body(message: &str) -> Option<xmltree::Element> {
+    let start = message.find('<')?;
+    let end = message.rfind('>')?;
+    if end < start {
+        return None;
+    }
+    xmltree::Element::parse(message[start..=end].as_bytes()).ok()
+}
+
+fn collect_code_values(element: &xmltree::Element, codes: &mut Vec<String>) {
+    for child in element.children.iter().filter_map(|node| node.as_element()) {
+        if child.name == "Value" {
+            if let Some(text) = child.get_text() {
+                codes.push(local_name(text.trim()).to_string());
+            }
+        }
+        collect_code_values(child, codes);
+    }
+}
+
+/// Words like `ter:InvalidArgVal`: a prefix and a capitalized local name.
+fn qualified_names(message: &str) -> Vec<String> {
+    message
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == ':' || c == '-' || c == '_'))
+        .filter_map(|word| {
+            let (prefix, name) = word.split_once(':')?;
+            let qualified = !prefix.is_empty()
+                && prefix
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && name.starts_with(|c: char| c.is_ascii_uppercase())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            qualified.then(|| name.to_string())
+        })
+        .collect()
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+impl DeviceError {
+    pub fn service_discovery(source: transport::Error) -> Self {
+        if is_timeout(&source) {
+            Self::Timeout
+        } else {
+            Self::ServiceDiscovery { source }
+        }
+    }
+
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::AuthenticationFailed { .. }
+                | Self::Transport(transport::Error::Authorization(_))
+                | Self::ServiceDiscovery {
+                    source: transport::Error::Authorization(_)
+                }
+        )
+    }
+
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(transport::Error::Authorization(_)) => false,
+            Self::Transport(_) | Self::Timeout => true,
+            Self::ServiceDiscovery { source } => is_transport_failure(source),
+            _ => false,
+        }
+    }
+
+    /// Whether the camera answered with a fault carrying `code`, see
+    /// `SoapFault::has_code`.
+    pub fn is_fault(&self, code: &str) -> bool {
+        matches!(self, Self::Fault(fault) if fault.has_code(code))
+    }
+}
+
+/// Failures to get an answer at all, as opposed to faults, bad credentials
+/// and messages that couldn't be encoded or decoded.
+fn is_transport_failure(e: &transport::Error) -> bool {
+    !matches!(
+        e,
+        transport::Error::Serialization(_)
+            | transport::Error::Deserialization(_)
+            | transport::Error::Authorization(_)
+            | transport::Error::Protocol(_)
+    )
+}
+
+/// The handshake error behind a failed HTTPS request, if the TLS layer is
+/// what failed.
+pub(crate) fn tls_failure(e: &reqwest::Error) -> Option<&native_tls::Error> {
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        if let Some(tls) = e.downcast_ref::<native_tls::Error>() {
+            return Some(tls);
+        }
+        source = e.source();
+    }
+    None
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCredentials => {
+                write!(f, "username and password must be specified together")
+            }
+            Self::AuthenticationFailed { username } => {
+                write!(f, "camera rejected the credentials of {}", username)
+            }
+            Self::MissingUri => write!(f, "uri must be specified"),
+            Self::Config(msg) => write!(f, "invalid config: {}", msg),
+            Self::UrlParse(e) => write!(f, "failed to parse uri: {}", e),
+            Self::ServiceDiscovery { source } => {
+                write!(f, "failed to discover services: {}", source)
+            }
+            Self::ServiceUriMismatch {
+                advertised,
+                expected,
+            } => write!(
+                f,
+                "service uri {} is not within expected uri {}",
+                advertised, expected
+            ),
+            Self::MissingService(service) => {
+                write!(f, "device does not provide a {} service", service)
+            }
+            Self::NoPtzNode => write!(f, "no PTZ node matches the media profile"),
+            Self::NoVideoSource => write!(f, "media profile has no video source"),
+            Self::NoProfiles => write!(f, "camera has no media profiles, create one first"),
+            Self::UnknownProfile {
+                requested,
+                available,
+            } => write!(
+                f,
+                "no media profile {}, available profiles: {}",
+                requested,
+                available.join(", ")
+            ),
+            Self::Unsupported(feature) => write!(f, "camera does not support {}", feature),
+            Self::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            Self::TooManyPresets => {
+                write!(f, "maximum number of presets reached on the PTZ node")
+            }
+            Self::NoHomePosition(fault) => write!(f, "no home position set: {}", fault),
+            Self::NtpEnabled => {
+                write!(f, "camera takes its time from NTP, disable NTP to set it")
+            }
+            Self::StillMoving(status) => {
+                write!(f, "camera still moving, last status: {:?}", status)
+            }
+            Self::Tls(msg) => write!(f, "TLS error: {}", msg),
+            Self::Timeout => write!(f, "request timed out"),
+            Self::UnknownDevice(name) => write!(f, "no device named {}", name),
+            Self::TaskFailed(msg) => write!(f, "device task failed: {}", msg),
+            Self::Transport(e) => write!(f, "transport error: {}", e),
+            Self::Fault(fault) => write!(f, "camera returned a fault: {}", fault.message),
+            Self::Encoding(e) => write!(f, "SOAP encoding error: {}", e),
+            Self::Http(msg) => write!(f, "HTTP error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UrlParse(e) => Some(e),
+            Self::ServiceDiscovery { source }
+            | Self::Transport(source)
+            | Self::Encoding(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<url::ParseError> for DeviceError {
+    fn from(e: url::ParseError) -> Self {
+        Self::UrlParse(e)
+    }
+}
+
+/// Only the timeout `ServiceClient` reports, fault text that mentions one is
+/// still a fault.
+fn is_timeout(e: &transport::Error) -> bool {
+    matches!(e, transport::Error::Protocol(msg) if msg == TIMED_OUT)
+}
+
+impl From<transport::Error> for DeviceError {
+    fn from(e: transport::Error) -> Self {
+        match e {
+            e if is_timeout(&e) => Self::Timeout,
+            transport::Error::Protocol(msg) => Self::Fault(SoapFault::parse(msg)),
+            e @ (transport::Error::Serialization(_) | transport::Error::Deserialization(_)) => {
+                Self::Encoding(e)
+            }
+            e => Self::Transport(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAULT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<env:Envelope xmlns:env="http://www.w3.org/2003/05/soap-envelope" xmlns:ter="http://www.onvif.org/ver10/error">
+  <env:Body>
+    <env:Fault>
+      <env:Code>
+        <env:Value>env:Sender</env:Value>
+        <env:Subcode>
+          <env:Value>ter:InvalidArgVal</env:Value>
+          <env:Subcode>
+            <env:Value>ter:NoProfile</env:Value>
+          </env:Subcode>
+        </env:Subcode>
+      </env:Code>
+      <env:Reason>
+        <env:Text xml:lang="en">Profile lookup timeout: no such token</env:Text>
+      </env:Reason>
+    </env:Fault>
+  </env:Body>
+</env:Envelope>"#;
+
+    #[test]
+    fn faults_are_not_retried() {
+        let e = DeviceError::from(transport::Error::Protocol(FAULT.to_string()));
+        assert!(matches!(e, DeviceError::Fault(_)), "{:?}", e);
+        assert!(!e.is_transient());
+    }
+
+    #[test]
+    fn fault_codes_are_parsed_from_the_body() {
+        let e = DeviceError::from(transport::Error::Protocol(FAULT.to_string()));
+        match e {
+            DeviceError::Fault(fault) => {
+                assert_eq!(fault.codes, ["Sender", "InvalidArgVal", "NoProfile"]);
+                assert!(fault.has_code("NoProfile"));
+                assert!(!fault.has_code("Profile"));
+            }
+            e => panic!("expected a fault, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn fault_codes_are_parsed_from_formatted_faults() {
+        let message = r#"Fault(Fault { code: "env:Receiver", subcode: "ter:Action/ter:TooManyPresets", reason: "Preset limit reached" })"#;
+        let e = DeviceError::from(transport::Error::Protocol(message.to_string()));
+        assert!(e.is_fault("TooManyPresets"));
+        assert!(!e.is_fault("Preset"));
+    }
+
+    #[test]
+    fn only_the_client_timeout_is_a_timeout() {
+        let e = DeviceError::from(transport::Error::Protocol(TIMED_OUT.to_string()));
+        assert!(matches!(e, DeviceError::Timeout));
+        assert!(e.is_transient());
+
+        let e = DeviceError::from(transport::Error::Protocol("request timed out".to_string()));
+        assert!(matches!(e, DeviceError::Fault(_)));
+    }
+
+    #[test]
+    fn encoding_errors_are_not_retried() {
+        let e = DeviceError::from(transport::Error::Deserialization("bad xml".to_string()));
+        assert!(matches!(e, DeviceError::Encoding(_)));
+        assert!(!e.is_transient());
+    }
+
+    #[test]
+    fn rejected_credentials_are_not_retried() {
+        let e = DeviceError::from(transport::Error::Authorization("Unauthorized".to_string()));
+        assert!(e.is_auth_failure());
+        assert!(!e.is_transient());
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use onvif::schema;
+use url::Url;
+
+use crate::device::{Device, Service};
+use crate::error::DeviceError;
+use crate::ptz::xsd_duration;
+use crate::trace::ServiceClient;
+
+impl Device {
+    pub fn supports_events(&self) -> bool {
+        self.connection().events.is_some()
+    }
+
+    /// Address of the event service, where pull point subscriptions are
+    /// created.
+    pub fn events_uri(&self) -> Option<Url> {
+        self.connection().events_uri.clone()
+    }
+
+    pub(crate) fn events_client(&self) -> Result<Service, DeviceError> {
+        self.service("events", |connection| connection.events.as_ref())
+    }
+}
+
+pub async fn get_event_properties(
+    device: &Device,
+) -> Result<schema::event::GetEventPropertiesResponse, DeviceError> {
+    let events = &*device.events_client()?;
+    Ok(schema::event::get_event_properties(events, &Default::default()).await?)
+}
+
+/// A pull point created by `subscribe_events`. Messages are fetched from it
+/// with `pull_messages` until the camera lets it expire.
+pub struct PullPointSubscription {
+    client: ServiceClient,
+}
+
+impl PullPointSubscription {
+    pub fn uri(&self) -> &Url {
+        self.client.uri()
+    }
+}
+
+/// One event, e.g. `tns1:VideoSource/MotionAlarm` with `State = true`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NotificationMessage {
+    pub topic: String,
+    pub utc_time: String,
+    /// What raised the event, e.g. `VideoSourceConfigurationToken`.
+    pub source: Vec<(String, String)>,
+    pub data: Vec<(String, String)>,
+}
+
+impl From<schema::b_2::NotificationMessageHolderType> for NotificationMessage {
+    fn from(holder: schema::b_2::NotificationMessageHolderType) -> Self {
+        let simple_items = |items: Option<schema::onvif::ItemList>| {
+            items
+                .map(|items| {
+                    items
+                        .simple_item
+                        .into_iter()
+                        .map(|item| (item.name, item.value))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let message = holder.message.msg;
+
+        Self {
+            topic: holder.topic.inner_text,
+            utc_time: message.utc_time.to_string(),
+            source: simple_items(message.source),
+            data: simple_items(message.data),
+        }
+    }
+}
+
+/// Longest wait `pull_messages` asks the camera for.
+pub const MAX_PULL_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub async fn subscribe_events(device: &Device) -> Result<PullPointSubscription, DeviceError> {
+    let events = &*device.events_client()?;
+    let response =
+        schema::event::create_pull_point_subscription(events, &Default::default()).await?;
+    // PullMessages is held open by the camera until events arrive.
+    let client = device.service_client(
+        &response.subscription_reference.address,
+        "events",
+        device.timeout() + MAX_PULL_TIMEOUT,
+    )?;
+    Ok(PullPointSubscription { client })
+}
+
+/// Wait up to `timeout`, at most `MAX_PULL_TIMEOUT`, for events, returning
+/// as soon as there are any.
+pub async fn pull_messages(
+    subscription: &PullPointSubscription,
+    timeout: Duration,
+) -> Result<Vec<NotificationMessage>, DeviceError> {
+    let timeout = timeout.min(MAX_PULL_TIMEOUT);
+    let response = schema::event::pull_messages(
+        &subscription.client,
+        &schema::event::PullMessages {
+            timeout: xsd_duration(timeout),
+            message_limit: 256,
+        },
+    )
+    .await?;
+
+    Ok(response
+        .notification_message
+        .into_iter()
+        .map(NotificationMessage::from)
+        .collect())
+}
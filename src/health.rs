@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use onvif::schema;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    Degraded,
+    Reconnecting,
+    /// The last reconnect failed. Calls keep going to the old clients, and
+    /// the next success brings the device back to `Connected`.
+    Disconnected,
+}
+
+pub(crate) struct Health {
+    state: watch::Sender<ConnectionState>,
+    // Held so that `send` keeps updating the value with no subscribers.
+    _state_rx: watch::Receiver<ConnectionState>,
+    consecutive_failures: AtomicU32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        let (state, _state_rx) = watch::channel(ConnectionState::Connected);
+        Self {
+            state,
+            _state_rx,
+            consecutive_failures: AtomicU32::new(0),
+        }
+    }
+}
+
+impl Health {
+    fn set_state(&self, state: ConnectionState) {
+        if *self.state.borrow() != state {
+            let _ = self.state.send(state);
+        }
+    }
+}
+
+impl Device {
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.health.state.borrow()
+    }
+
+    /// Get notified whenever the device moves between connected, degraded,
+    /// reconnecting and disconnected, e.g. to grey out PTZ controls.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.health.state.subscribe()
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.health.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Record the outcome of a call so repeated failures mark the device as
+    /// degraded and successes bring it back.
+    pub fn track<T>(&self, result: Result<T, DeviceError>) -> Result<T, DeviceError> {
+        match result {
+            Ok(_) => {
+                self.health.consecutive_failures.store(0, Ordering::Relaxed);
+                self.health.set_state(ConnectionState::Connected);
+            }
+            Err(ref e) if e.is_transient() => {
+                self.health
+                    .consecutive_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                self.health.set_state(ConnectionState::Degraded);
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// Cheap liveness probe. GetSystemDateAndTime needs no authentication so
+    /// this only fails when the camera is unreachable.
+    pub async fn health_check(&self) -> Result<(), DeviceError> {
+        let result =
+            schema::devicemgmt::get_system_date_and_time(&*self.device_mgmt(), &Default::default())
+                .await
+                .map(|_| ())
+                .map_err(DeviceError::from);
+        self.track(result)
+    }
+
+    /// Re-run service discovery and swap in new clients, e.g. after the
+    /// camera rebooted. Calls already under way finish on the old clients.
+    /// On failure the old clients stay and the state is `Disconnected`.
+    pub async fn reconnect(&self) -> Result<(), DeviceError> {
+        self.health.set_state(ConnectionState::Reconnecting);
+        let fresh = match self.config.clone().build().await {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                self.health.set_state(ConnectionState::Disconnected);
+                return Err(e);
+            }
+        };
+
+        *self.connection.write().unwrap() = fresh.connection();
+        *self.move_spaces.lock().unwrap() = fresh.move_spaces.into_inner().unwrap();
+        *self.emulate_relative.lock().unwrap() = fresh.emulate_relative.into_inner().unwrap();
+        *self.profile_token.lock().unwrap() = fresh.profile_token.into_inner().unwrap();
+        *self.info.lock().unwrap() = fresh.info.into_inner().unwrap();
+        *self.capabilities.write().unwrap() = fresh.capabilities.into_inner().unwrap();
+        // Looked up again, in case the information failed to load before.
+        *self.quirks.lock().unwrap() = None;
+
+        self.health.consecutive_failures.store(0, Ordering::Relaxed);
+        self.health.set_state(ConnectionState::Connected);
+        Ok(())
+    }
+}
+
+/// Spawn a task that reconnects `device` once `threshold` consecutive calls
+/// have failed, checking every `interval`.
+pub fn spawn_reconnect_task(
+    device: Arc<Device>,
+    threshold: u32,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let failing = device.consecutive_failures() >= threshold
+                || (device.connection_state() != ConnectionState::Connected
+                    && device.health_check().await.is_err());
+            if !failing {
+                continue;
+            }
+
+            if let Err(e) = device.reconnect().await {
+                warn!("reconnect failed: {}", e);
+            }
+        }
+    })
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::ptz::{self, StopAxes};
+
+/// Timeout of each continuous move of a hold. Short, so the camera stops by
+/// itself soon after the renewals do.
+pub const HOLD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shortest timeout `start_with_timeout` accepts. Below this a renewal can't
+/// make the round trip before the previous move runs out.
+pub const MIN_HOLD_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A continuous move renewed every half timeout until released, for press
+/// and hold controls. Dropping it without `release` still sends the stop,
+/// and if this side dies entirely the camera stops when the last move times
+/// out.
+pub struct ContinuousMoveHold {
+    device: Arc<Device>,
+    cancel: CancellationToken,
+    renewals: Option<JoinHandle<()>>,
+}
+
+impl ContinuousMoveHold {
+    pub async fn start(
+        device: Arc<Device>,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+    ) -> Result<Self, DeviceError> {
+        Self::start_with_timeout(device, pan, tilt, zoom, HOLD_TIMEOUT).await
+    }
+
+    pub async fn start_with_timeout(
+        device: Arc<Device>,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        timeout: Duration,
+    ) -> Result<Self, DeviceError> {
+        if timeout < MIN_HOLD_TIMEOUT {
+            return Err(DeviceError::InvalidArgument(format!(
+                "hold timeout {:?} is below {:?}",
+                timeout, MIN_HOLD_TIMEOUT
+            )));
+        }
+        ptz::send_continuous_ptz(&device, pan, tilt, zoom, Some(timeout), false, None).await?;
+
+        let cancel = CancellationToken::new();
+        let renewals = tokio::spawn(renew(
+            device.clone(),
+            (pan, tilt, zoom),
+            timeout,
+            cancel.clone(),
+        ));
+        Ok(Self {
+            device,
+            cancel,
+            renewals: Some(renewals),
+        })
+    }
+
+    /// Stop renewing and stop the camera.
+    pub async fn release(mut self) -> Result<(), DeviceError> {
+        self.cancel.cancel();
+        if let Some(renewals) = self.renewals.take() {
+            // A renewal still in flight would otherwise land after the stop.
+            let _ = renewals.await;
+        }
+        ptz::send_stop_ptz(&self.device, StopAxes::All, None).await
+    }
+}
+
+impl Drop for ContinuousMoveHold {
+    fn drop(&mut self) {
+        let renewals = match self.renewals.take() {
+            Some(renewals) => renewals,
+            // Released.
+            None => return,
+        };
+        self.cancel.cancel();
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        let device = self.device.clone();
+        runtime.spawn(async move {
+            let _ = renewals.await;
+            match ptz::send_stop_ptz(&device, StopAxes::All, None).await {
+                Ok(()) => debug!("ptz stopped after a dropped hold"),
+                Err(e) => warn!("failed to stop after a dropped hold: {}", e),
+            }
+        });
+    }
+}
+
+async fn renew(
+    device: Arc<Device>,
+    (pan, tilt, zoom): (f64, f64, f64),
+    timeout: Duration,
+    cancel: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(timeout / 2);
+    // The first tick is immediate, the move was just sent.
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        let resend = ptz::send_continuous_ptz(&device, pan, tilt, zoom, Some(timeout), false, None);
+        let result = tokio::select! {
+            _ = cancel.cancelled() => return,
+            result = resend => result,
+        };
+        // Keep trying, the next renewal may get through before the timeout.
+        if let Err(e) = result {
+            warn!("failed to renew held move: {}", e);
+        }
+    }
+}
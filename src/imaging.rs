@@ -0,0 +1,71 @@
+use onvif::schema;
+use tracing::info;
+
+use crate::device::{Device, Service};
+use crate::error::DeviceError;
+use crate::media::get_profile;
+
+impl Device {
+    pub fn imaging(&self) -> Result<Service, DeviceError> {
+        self.service("imaging", |connection| connection.imaging.as_ref())
+    }
+}
+
+/// Video source of the first profile, which imaging calls are addressed to.
+pub async fn get_video_source_token(
+    device: &Device,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    get_profile(device)
+        .await?
+        .video_source_configuration
+        .map(|config| config.source_token)
+        .ok_or(DeviceError::NoVideoSource)
+}
+
+pub async fn get_imaging_settings(
+    device: &Device,
+) -> Result<schema::onvif::ImagingSettings20, DeviceError> {
+    let imaging = &*device.imaging()?;
+    let video_source_token = get_video_source_token(device).await?;
+
+    Ok(schema::imaging::get_imaging_settings(
+        imaging,
+        &schema::imaging::GetImagingSettings { video_source_token },
+    )
+    .await?
+    .imaging_settings)
+}
+
+/// Start a continuous focus move, negative speeds focus nearer.
+pub async fn focus_move(device: &Device, speed: f64) -> Result<(), DeviceError> {
+    let imaging = &*device.imaging()?;
+    info!("focus move: {}", speed);
+    schema::imaging::move_(
+        imaging,
+        &schema::imaging::Move {
+            video_source_token: get_video_source_token(device).await?,
+            focus: schema::onvif::FocusMove {
+                absolute: None,
+                relative: None,
+                continuous: Some(schema::onvif::ContinuousFocus { speed }),
+            },
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn focus_stop(device: &Device) -> Result<(), DeviceError> {
+    let imaging = &*device.imaging()?;
+    info!("focus stop");
+    schema::imaging::stop(
+        imaging,
+        &schema::imaging::Stop {
+            video_source_token: get_video_source_token(device).await?,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
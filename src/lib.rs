@@ -0,0 +1,27 @@
+pub mod auxiliary;
+pub mod capabilities;
+pub mod config;
+pub mod device;
+pub mod discovery;
+pub mod encoder;
+pub mod error;
+pub mod events;
+pub mod health;
+pub mod hold;
+pub mod imaging;
+pub mod manager;
+pub mod media;
+pub mod presets;
+pub mod ptz;
+pub mod ptz_config;
+pub(crate) mod queue;
+pub mod quirks;
+pub mod retry;
+pub mod session;
+pub mod system;
+pub mod tours;
+pub mod trace;
+
+pub use device::{AuthMode, Device, DeviceBuilder, DeviceInfo, DiscoveryPath};
+pub use error::DeviceError;
+pub use manager::DeviceManager;
@@ -1,227 +1,600 @@
-#![allow(dead_code)]
-use std::str::FromStr;
+use clap::{ArgEnum, Parser, Subcommand};
+use onvif::schema;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing_subscriber::EnvFilter;
 
-use async_std::task;
-use onvif::{schema, soap};
+use test_ptz::{
+    auxiliary, capabilities,
+    config::Config,
+    discovery, encoder, events, imaging, media, presets, ptz, ptz_config,
+    quirks::QuirksRegistry,
+    system, tours,
+    trace::{FileSink, StderrSink},
+    AuthMode, Device, DeviceBuilder, DeviceError,
+};
 use url::Url;
 
-struct Device {
-    pub device_mgmt: soap::client::Client,
-    pub media: Option<soap::client::Client>,
-    pub ptz: Option<soap::client::Client>,
-}
+#[derive(Parser)]
+#[clap(about = "Poke at the PTZ service of an ONVIF camera")]
+struct Args {
+    /// Probe the local network for cameras and exit.
+    #[clap(long)]
+    discover: bool,
 
-const RELATIVE_BLACKLIST: &str = "IPD-E24Y00";
-
-impl Device {
-    pub fn new(url: Option<Url>, usr: Option<String>, pwd: Option<String>) -> Result<Self, String> {
-        let creds = match (usr, pwd) {
-            (Some(usr), Some(pwd)) => Some(soap::client::Credentials {
-                username: usr,
-                password: pwd,
-            }),
-            (None, None) => None,
-            _ => panic!("Username and password must be specified together"),
-        };
+    /// Base URL of the camera, e.g. http://192.168.1.15:888. Without it the
+    /// camera is taken from the config file.
+    #[clap(long, alias = "uri", env = "ONVIF_URL")]
+    url: Option<Url>,
 
-        let base_uri = url.as_ref().ok_or_else(|| "uri must be specified")?;
+    /// Camera config file, defaults to ~/.config/test-ptz/config.toml
+    #[clap(long)]
+    config: Option<PathBuf>,
 
-        let device_mgmt_uri = base_uri.join("onvif/device_service").unwrap();
+    /// Name of the camera in the config file, defaults to the first one.
+    #[clap(long, conflicts_with = "url")]
+    camera: Option<String>,
 
-        let mut out = Self {
-            device_mgmt: soap::client::ClientBuilder::new(&device_mgmt_uri)
-                .credentials(creds.clone())
-                .build(),
-            media: None,
-            ptz: None,
-        };
+    #[clap(long, env = "ONVIF_USER")]
+    user: Option<String>,
 
-        let services = task::block_on(schema::devicemgmt::get_services(
-            &out.device_mgmt,
-            &Default::default(),
-        ))
-        .unwrap();
-
-        for s in &services.service {
-            let url = Url::parse(&s.x_addr).map_err(|e| e.to_string())?;
-            if !url.as_str().starts_with(base_uri.as_str()) {
-                return Err(format!(
-                    "Service URI {} is not within base URI {}",
-                    &s.x_addr, &base_uri
-                ));
-            }
+    /// Prompted for when a user is given without one.
+    #[clap(
+        long,
+        alias = "password",
+        env = "ONVIF_PASS",
+        hide_env_values = true,
+        requires = "user"
+    )]
+    pass: Option<String>,
 
-            let svc = Some(
-                soap::client::ClientBuilder::new(&url)
-                    .credentials(creds.clone())
-                    .build(),
-            );
+    /// Authentication scheme: auto, anonymous, username-token, digest or basic.
+    #[clap(long)]
+    auth: Option<AuthMode>,
 
-            match s.namespace.as_str() {
-                "http://www.onvif.org/ver10/device/wsdl" => {
-                    if s.x_addr != device_mgmt_uri.as_str() {
-                        return Err(format!(
-                            "advertised device mgmt uri {} not expected {}",
-                            &s.x_addr, &device_mgmt_uri
-                        ));
-                    }
-                }
-                "http://www.onvif.org/ver10/media/wsdl" => out.media = svc,
-                "http://www.onvif.org/ver20/ptz/wsdl" => out.ptz = svc,
-                _ => {}
-            }
-        }
+    /// Accept self-signed certificates on https cameras.
+    #[clap(long)]
+    insecure: bool,
 
-        Ok(out)
-    }
+    /// Send every request to the host and port of --url, whatever service
+    /// addresses the camera advertises. For cameras behind NAT.
+    #[clap(long)]
+    rewrite_xaddrs: bool,
+
+    /// Print every SOAP request and response, with passwords redacted.
+    #[clap(long)]
+    trace_soap: bool,
+
+    /// Write the SOAP trace to a file instead of stderr.
+    #[clap(long)]
+    trace_file: Option<PathBuf>,
+
+    /// JSON file of model quirks, searched before the built-in ones.
+    #[clap(long)]
+    quirks: Option<PathBuf>,
+
+    /// Name of the media profile to move, defaults to the first one.
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// Output of the summary, status and presets: text or json.
+    #[clap(long, arg_enum, default_value = "text")]
+    format: Format,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-async fn get_profile_token(device: &Device) -> schema::onvif::ReferenceToken {
-    let media_client = device.media.as_ref().unwrap();
-    let profile = &schema::media::get_profiles(media_client, &Default::default())
-        .await
-        .unwrap()
-        .profiles[0];
-    schema::onvif::ReferenceToken(profile.token.0.clone())
+#[derive(ArgEnum, Clone, Copy)]
+enum Format {
+    Text,
+    Json,
 }
 
-async fn send_continuous_ptz(device: &Device, pan: f64, tilt: f64, zoom: f64) {
-    if let Some(ref ptz) = device.ptz {
-        let profile_token = get_profile_token(device).await;
-
-        println!("continuous pan: {}, tilt: {}, zoom: {}", pan, tilt, zoom);
-        let pan_tilt = Some(schema::common::Vector2D {
-            x: pan,
-            y: tilt,
-            space: None,
-        });
-        let zoom = Some(schema::common::Vector1D {
-            x: zoom,
-            space: None,
-        });
-        let velocity = schema::onvif::Ptzspeed { pan_tilt, zoom };
-        let timeout: xsd_types::types::duration::Duration =
-            xsd_types::types::duration::Duration::from_str("PT5S").unwrap();
-
-        schema::ptz::continuous_move(
-            ptz,
-            &schema::ptz::ContinuousMove {
-                profile_token,
-                velocity,
-                timeout: Some(timeout),
+impl Format {
+    fn print<T: std::fmt::Debug + serde::Serialize>(self, value: &T) {
+        match self {
+            Self::Text => println!("{:#?}", value),
+            Self::Json => match serde_json::to_string(value) {
+                Ok(json) => println!("{}", json),
+                Err(error) => eprintln!("Failed to serialize output: {}", error),
             },
-        )
-        .await
-        .unwrap();
+        }
     }
 }
 
-async fn send_stop_ptz(device: &Device) {
-    if let Some(ref ptz) = device.ptz {
-        println!(
-            "ptz stop: {:#?}",
-            schema::ptz::stop(
-                ptz,
-                &schema::ptz::Stop {
-                    profile_token: get_profile_token(device).await,
-                    pan_tilt: Some(true),
-                    zoom: Some(true)
+#[derive(Subcommand)]
+enum Command {
+    /// Start a continuous move at the given velocity.
+    #[clap(allow_negative_numbers = true)]
+    Continuous {
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        /// Scale pan and tilt onto the unit circle, for joystick input.
+        #[clap(long)]
+        normalize: bool,
+    },
+    /// Move by the given translation.
+    #[clap(allow_negative_numbers = true)]
+    Relative {
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        /// Speed on every axis, defaults to the camera's.
+        #[clap(long)]
+        speed: Option<f64>,
+        /// Wait for the camera to stop and print where it ended up.
+        #[clap(long)]
+        wait: bool,
+    },
+    /// Move to the given position.
+    #[clap(allow_negative_numbers = true)]
+    Absolute {
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        /// Wait for the camera to stop and print where it ended up.
+        #[clap(long)]
+        wait: bool,
+    },
+    /// Zoom at the given velocity, negative to zoom out.
+    #[clap(allow_negative_numbers = true)]
+    Zoom { velocity: f64 },
+    /// Zoom to a level from 0 (wide) to 1 (tele).
+    ZoomTo { level: f64 },
+    /// Stop movement: pan-tilt, zoom or all.
+    Stop {
+        #[clap(default_value = "all")]
+        axes: ptz::StopAxes,
+    },
+    /// Print the current PTZ position and move status.
+    Status,
+    /// Move to the home position, optionally at the given speed.
+    Home { speed: Option<f64> },
+    /// Save the current position as the home position.
+    SetHome,
+    /// List the presets of the profile.
+    Presets,
+    /// Move to the preset with the given token.
+    GotoPreset { token: String },
+    /// Save the current position as a preset, replacing one of the same name.
+    SetPreset { name: String },
+    /// Delete the preset with the given token.
+    RemovePreset { token: String },
+    /// List the tours stored on the camera.
+    Tours,
+    /// Start, stop or pause a tour stored on the camera.
+    Tour {
+        token: String,
+        operation: tours::TourOperation,
+    },
+    /// List the auxiliary commands of the PTZ node.
+    AuxCommands,
+    /// Send an auxiliary command, e.g. "tt:Wiper|On", and print the response.
+    Aux { command: String },
+    /// Print the video encoder configurations.
+    Encoders,
+    /// Change a video encoder configuration, keeping what isn't given.
+    SetEncoder {
+        token: String,
+        /// e.g. 1920x1080
+        #[clap(long, parse(try_from_str = parse_resolution))]
+        resolution: Option<(i32, i32)>,
+        #[clap(long)]
+        bitrate_kbps: Option<i32>,
+        #[clap(long)]
+        framerate: Option<i32>,
+        #[clap(long)]
+        gop: Option<i32>,
+    },
+    /// Change the default speed, timeout and limits of the profile's PTZ
+    /// configuration, keeping what isn't given.
+    #[clap(allow_negative_numbers = true)]
+    SetPtzConfig {
+        /// e.g. 0.5,0.5
+        #[clap(long, parse(try_from_str = parse_pair))]
+        pan_tilt_speed: Option<(f64, f64)>,
+        #[clap(long)]
+        zoom_speed: Option<f64>,
+        /// Seconds until a continuous move stops on its own.
+        #[clap(long)]
+        timeout: Option<f64>,
+        /// e.g. -0.5,0.5
+        #[clap(long, parse(try_from_str = parse_pair))]
+        pan_limits: Option<(f64, f64)>,
+        #[clap(long, parse(try_from_str = parse_pair))]
+        tilt_limits: Option<(f64, f64)>,
+        #[clap(long, parse(try_from_str = parse_pair))]
+        zoom_limits: Option<(f64, f64)>,
+    },
+    /// Print the on-screen display overlays.
+    Osds,
+    /// Set the text of a plain-text on-screen display overlay.
+    SetOsdText { token: String, text: String },
+    /// Print the imaging settings of the first video source.
+    Imaging,
+    /// Start a continuous focus move, negative speeds focus nearer.
+    #[clap(allow_negative_numbers = true)]
+    Focus { speed: f64 },
+    /// Stop any focus movement.
+    FocusStop,
+    /// Print the event topics the camera can raise.
+    Events,
+    /// Subscribe to events and print them as they arrive.
+    Watch,
+    /// Reboot the camera.
+    Reboot,
+    /// Print the camera clock.
+    Time,
+    /// Set the camera clock to this host's clock.
+    SyncTime,
+}
+
+fn parse_resolution(s: &str) -> Result<(i32, i32), String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {}", s))?;
+    let parse = |n: &str| n.parse::<i32>().map_err(|e| format!("{}: {}", n, e));
+    Ok((parse(width)?, parse(height)?))
+}
+
+fn parse_pair(s: &str) -> Result<(f64, f64), String> {
+    let (a, b) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected A,B, got {}", s))?;
+    let parse = |n: &str| n.trim().parse::<f64>().map_err(|e| format!("{}: {}", n, e));
+    Ok((parse(a)?, parse(b)?))
+}
+
+async fn print_discovered_devices() {
+    match discovery::discover(std::time::Duration::from_secs(2)).await {
+        Ok(devices) => {
+            for device in devices.iter().filter(|d| d.is_network_video_transmitter()) {
+                println!("{}", device.endpoint_reference);
+                for x_addr in &device.x_addrs {
+                    println!("  xaddr: {}", x_addr);
                 }
-            )
-            .await
-            .unwrap()
-        );
+                for scope in &device.scopes {
+                    println!("  scope: {}", scope);
+                }
+            }
+        }
+        Err(error) => eprintln!("Failed to discover devices: {}", error),
     }
 }
 
-async fn send_relative_ptz(device: &Device, pan: f64, tilt: f64, zoom: f64) {
-    if let Some(ref ptz) = device.ptz {
-        println!("relative pan: {}, tilt: {}, zoom: {}", pan, tilt, zoom);
-        let space = Some("relative_pan_tilt_translation_space".to_string());
-        let pan_tilt = Some(schema::common::Vector2D {
-            x: pan,
-            y: tilt,
-            space,
-        });
-        let space = Some("relative_zoom_translation_space".to_string());
-        let zoom = Some(schema::common::Vector1D { x: zoom, space });
-        let translation = schema::onvif::Ptzvector { pan_tilt, zoom };
-        let speed = None;
-
-        println!(
-            "ptz relative move: {:#?}",
-            schema::ptz::relative_move(
-                ptz,
-                &schema::ptz::RelativeMove {
-                    profile_token: get_profile_token(&device).await,
-                    translation,
-                    speed
-                }
-            )
-            .await
-            .unwrap()
-        );
+fn device_builder(args: &mut Args) -> Result<DeviceBuilder, DeviceError> {
+    if let Some(url) = args.url.take() {
+        let password = match (&args.user, args.pass.take()) {
+            (Some(_), None) => Some(
+                rpassword::prompt_password_stderr("Password: ")
+                    .map_err(|e| DeviceError::Config(format!("reading password: {}", e)))?,
+            ),
+            (_, password) => password,
+        };
+        let mut builder = Device::builder().uri(url);
+        if let (Some(user), Some(password)) = (args.user.take(), password) {
+            builder = builder.credentials(user, password);
+        }
+        return Ok(builder);
+    }
+
+    let path = args
+        .config
+        .clone()
+        .or_else(Config::default_path)
+        .ok_or(DeviceError::MissingUri)?;
+    let config = Config::load(&path)?;
+    let camera = match args.camera {
+        Some(ref name) => config
+            .camera(name)
+            .ok_or_else(|| DeviceError::UnknownDevice(name.clone()))?,
+        None => config.cameras.first().ok_or(DeviceError::MissingUri)?,
+    };
+    Ok(config.builder(camera))
+}
+
+async fn connect(args: &mut Args) -> Result<Device, DeviceError> {
+    let mut builder = device_builder(args)?;
+    if let Some(auth_mode) = args.auth {
+        builder = builder.auth_mode(auth_mode);
+    }
+    if args.insecure {
+        builder = builder.insecure_skip_verify(true);
     }
+    if args.rewrite_xaddrs {
+        builder = builder.rewrite_xaddrs(true);
+    }
+    if let Some(ref path) = args.quirks {
+        builder = builder.quirks(QuirksRegistry::load(path)?);
+    }
+    if let Some(ref path) = args.trace_file {
+        let sink = FileSink::create(path)
+            .map_err(|e| DeviceError::Config(format!("{}: {}", path.display(), e)))?;
+        builder = builder.trace_soap(Arc::new(sink));
+    } else if args.trace_soap {
+        builder = builder.trace_soap(Arc::new(StderrSink));
+    }
+    builder.build().await
 }
 
-fn translate_recenter(
+async fn print_device_summary(device: &Device, format: Format) {
+    match capabilities::get_capabilities(device).await {
+        Ok(capabilities) => format.print(&capabilities),
+        Err(error) => eprintln!("Failed to fetch capabilities: {}", error),
+    };
+
+    match device.info().await {
+        Ok(info) => format.print(&info),
+        Err(error) => eprintln!("Failed to fetch information: {}", error),
+    }
+
+    if device.media_client().is_err() && device.media2_client().is_err() {
+        eprintln!("Camera has no media service, PTZ and stream commands are unavailable");
+    }
+
+    if let Ok(ptz) = device.ptz_client() {
+        // Raw ONVIF types, not serializable.
+        if let Format::Text = format {
+            match schema::ptz::get_configurations(&*ptz, &schema::ptz::GetConfigurations {}).await {
+                Ok(config) => println!("{:#?}", config),
+                Err(error) => eprintln!("Failed to fetch PTZ configurations: {}", error),
+            }
+        }
+        match ptz::get_ptz_status(device).await {
+            Ok(status) => format.print(&status),
+            Err(error) => eprintln!("Failed to fetch PTZ status: {}", error),
+        }
+    }
+}
+
+async fn run_command(
     device: &Device,
-    onvif_model: Option<String>,
-    x: i32,
-    y: i32,
-    rect_width: i32,
-    rect_height: i32,
-) {
-    let pan = x as f64 / rect_width as f64;
-    let tilt = -y as f64 / rect_height as f64;
-    let zoom = 0.0;
-
-    task::block_on(async {
-        // if onvif_model
-        //     .unwrap_or("".to_string())
-        //     .eq_ignore_ascii_case(RELATIVE_BLACKLIST)
-        // {
-        send_continuous_ptz(device, pan, -tilt, zoom).await;
-        let time = (500.0 * (pan * pan + tilt * tilt).sqrt()) as u64;
-        async_std::task::sleep(std::time::Duration::from_millis(time)).await;
-        send_stop_ptz(&device).await;
-        // } else {
-        //     send_relative_ptz(&device, pan, tilt, zoom).await;
-        // }
-    });
+    profile: Option<&str>,
+    format: Format,
+    command: Command,
+) -> Result<(), DeviceError> {
+    let profile = match profile {
+        Some(name) => Some(media::profile_token_by_name(device, name).await?),
+        None => None,
+    };
+
+    match command {
+        Command::Continuous {
+            pan,
+            tilt,
+            zoom,
+            normalize,
+        } => {
+            let timeout = device.continuous_timeout();
+            ptz::send_continuous_ptz(device, pan, tilt, zoom, timeout, normalize, profile).await
+        }
+        Command::Relative {
+            pan,
+            tilt,
+            zoom,
+            speed,
+            wait: false,
+        } => {
+            let speed = speed.map(|speed| (speed, speed, speed));
+            ptz::send_relative_ptz(device, pan, tilt, zoom, speed, profile).await
+        }
+        Command::Relative {
+            pan,
+            tilt,
+            zoom,
+            speed,
+            wait: true,
+        } => {
+            let speed = speed.map(|speed| (speed, speed, speed));
+            let status =
+                ptz::send_relative_ptz_and_wait(device, pan, tilt, zoom, speed, profile).await?;
+            format.print(&status);
+            Ok(())
+        }
+        Command::Absolute {
+            pan,
+            tilt,
+            zoom,
+            wait: false,
+        } => ptz::send_absolute_ptz(device, pan, tilt, zoom, None, profile).await,
+        Command::Absolute {
+            pan,
+            tilt,
+            zoom,
+            wait: true,
+        } => {
+            let status =
+                ptz::send_absolute_ptz_and_wait(device, pan, tilt, zoom, None, profile).await?;
+            format.print(&status);
+            Ok(())
+        }
+        Command::Zoom { velocity } => ptz::zoom_continuous(device, velocity).await,
+        Command::ZoomTo { level } => ptz::zoom_absolute(device, level).await,
+        Command::Stop { axes } => ptz::send_stop_ptz(device, axes, profile).await,
+        Command::Status => {
+            format.print(&ptz::get_ptz_status(device).await?);
+            Ok(())
+        }
+        Command::Home { speed } => {
+            let speed = speed.map(|speed| schema::onvif::Ptzspeed {
+                pan_tilt: Some(schema::common::Vector2D {
+                    x: speed,
+                    y: speed,
+                    space: None,
+                }),
+                zoom: Some(schema::common::Vector1D {
+                    x: speed,
+                    space: None,
+                }),
+            });
+            ptz::goto_home(device, speed).await
+        }
+        Command::SetHome => ptz::set_home(device).await,
+        Command::Presets => {
+            let presets = presets::list_presets(device).await?;
+            match format {
+                Format::Text => presets.iter().for_each(|preset| println!("{}", preset)),
+                Format::Json => format.print(&presets),
+            }
+            Ok(())
+        }
+        Command::GotoPreset { token } => {
+            presets::goto_preset(device, schema::onvif::ReferenceToken(token), None).await
+        }
+        Command::SetPreset { name } => {
+            let token = presets::set_preset(device, &name).await?;
+            println!("{}", token.0);
+            Ok(())
+        }
+        Command::RemovePreset { token } => {
+            presets::remove_preset(device, schema::onvif::ReferenceToken(token)).await
+        }
+        Command::Tours => {
+            for tour in tours::get_preset_tours(device).await? {
+                println!("{:?}", tour);
+            }
+            Ok(())
+        }
+        Command::Tour { token, operation } => {
+            tours::operate_preset_tour(device, &token, operation).await
+        }
+        Command::AuxCommands => {
+            for command in auxiliary::list_auxiliary_commands(device).await? {
+                println!("{}", command);
+            }
+            Ok(())
+        }
+        Command::Aux { command } => {
+            println!(
+                "{}",
+                auxiliary::send_auxiliary_command(device, &command).await?
+            );
+            Ok(())
+        }
+        Command::Encoders => {
+            println!("{:#?}", encoder::get_video_encoder_configs(device).await?);
+            Ok(())
+        }
+        Command::SetEncoder {
+            token,
+            resolution,
+            bitrate_kbps,
+            framerate,
+            gop,
+        } => {
+            let params = encoder::SetEncoderParams {
+                resolution,
+                bitrate_kbps,
+                framerate,
+                gop,
+            };
+            encoder::set_video_encoder(device, &token, params).await
+        }
+        Command::SetPtzConfig {
+            pan_tilt_speed,
+            zoom_speed,
+            timeout,
+            pan_limits,
+            tilt_limits,
+            zoom_limits,
+        } => {
+            let timeout = match timeout {
+                Some(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+                    Some(std::time::Duration::from_secs_f64(seconds))
+                }
+                Some(seconds) => {
+                    return Err(DeviceError::InvalidArgument(format!(
+                        "timeout {} is not a number of seconds",
+                        seconds
+                    )))
+                }
+                None => None,
+            };
+            let params = ptz_config::SetPtzConfigParams {
+                pan_tilt_speed,
+                zoom_speed,
+                timeout,
+                pan_limits,
+                tilt_limits,
+                zoom_limits,
+            };
+            ptz_config::set_ptz_configuration(device, profile, params).await
+        }
+        Command::Osds => {
+            println!("{:#?}", media::get_osds(device, None).await?);
+            Ok(())
+        }
+        Command::SetOsdText { token, text } => media::set_osd_text(device, &token, &text).await,
+        Command::Imaging => {
+            println!("{:#?}", imaging::get_imaging_settings(device).await?);
+            Ok(())
+        }
+        Command::Focus { speed } => imaging::focus_move(device, speed).await,
+        Command::FocusStop => imaging::focus_stop(device).await,
+        Command::Events => {
+            println!("{:#?}", events::get_event_properties(device).await?);
+            Ok(())
+        }
+        Command::Time => {
+            println!("{:#?}", system::get_system_datetime(device).await?);
+            Ok(())
+        }
+        Command::SyncTime => system::set_system_datetime(device, chrono::Utc::now(), None).await,
+        Command::Watch => {
+            let subscription = events::subscribe_events(device).await?;
+            loop {
+                let timeout = std::time::Duration::from_secs(10);
+                for message in events::pull_messages(&subscription, timeout).await? {
+                    println!("{:?}", message);
+                }
+            }
+        }
+        Command::Reboot => {
+            println!("{}", system::reboot(device).await?);
+            Ok(())
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let uri = Url::parse("http://192.168.1.15:888").unwrap();
-    let device = Device::new(
-        Some(uri),
-        Some("test".to_owned()),
-        Some("test123".to_owned()),
-    )
-    .unwrap();
-
-    async_std::task::block_on(async {
-        match schema::devicemgmt::get_capabilities(&device.device_mgmt, &Default::default()).await {
-            Ok(capabilities) => println!("{:#?}", capabilities),
-            Err(error) => println!("Failed to fetch capabilities: {}", error.to_string()),
-        };
+    // Plain lines on stderr, so stdout only carries command output and stays
+    // parseable with --format json. RUST_LOG=debug for more.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .without_time()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
 
-        match schema::devicemgmt::get_device_information(&device.device_mgmt, &Default::default())
-            .await
-        {
-            Ok(info) => println!("{:#?}", info),
-            Err(error) => println!("Failed to fetch information: {}", error.to_string()),
-        }
+    let mut args = Args::parse();
 
-        if let Some(ref ptz) = device.ptz {
-            let config = schema::ptz::get_configurations(ptz, &schema::ptz::GetConfigurations {})
-                .await
-                .unwrap();
-            println!("{:#?}", config);
+    if args.discover {
+        print_discovered_devices().await;
+        return;
+    }
+
+    let device = match connect(&mut args).await {
+        Ok(device) => device,
+        Err(error) => {
+            eprintln!("Failed to connect: {}", error);
+            std::process::exit(1);
         }
+    };
 
-        // send_continuous_ptz(&device, -0.5, 0.0, 0.0).await;
-        // send_relative_ptz(&device, 0.5, 0.0, 0.0).await;
-    });
+    match args.command {
+        Some(command) => {
+            if let Err(error) =
+                run_command(&device, args.profile.as_deref(), args.format, command).await
+            {
+                eprintln!("Command failed: {}", error);
+                std::process::exit(1);
+            }
+        }
+        None => print_device_summary(&device, args.format).await,
+    }
 }
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use onvif::schema;
+
+use crate::device::{Device, DeviceBuilder};
+use crate::error::DeviceError;
+use crate::presets;
+use crate::ptz;
+
+/// Owns a set of named cameras and fans PTZ commands out to them.
+#[derive(Default)]
+pub struct DeviceManager {
+    devices: RwLock<HashMap<String, Arc<Device>>>,
+}
+
+impl DeviceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect to every camera concurrently. Cameras that fail to connect are
+    /// returned alongside the manager instead of failing the whole set.
+    pub async fn connect_all(
+        cameras: impl IntoIterator<Item = (String, DeviceBuilder)>,
+    ) -> (Self, Vec<(String, DeviceError)>) {
+        let manager = Self::new();
+        let pending: Vec<_> = cameras
+            .into_iter()
+            .map(|(name, builder)| (name, tokio::spawn(builder.build())))
+            .collect();
+
+        let mut failures = vec![];
+        for (name, handle) in pending {
+            match handle.await {
+                Ok(Ok(device)) => manager.insert(name, device),
+                Ok(Err(e)) => failures.push((name, e)),
+                Err(e) => failures.push((name, DeviceError::TaskFailed(e.to_string()))),
+            }
+        }
+
+        (manager, failures)
+    }
+
+    pub async fn add(
+        &self,
+        name: impl Into<String>,
+        builder: DeviceBuilder,
+    ) -> Result<(), DeviceError> {
+        let device = builder.build().await?;
+        self.insert(name, device);
+        Ok(())
+    }
+
+    pub fn insert(&self, name: impl Into<String>, device: Device) {
+        self.devices
+            .write()
+            .unwrap()
+            .insert(name.into(), Arc::new(device));
+    }
+
+    pub fn remove(&self, name: &str) -> Option<Arc<Device>> {
+        self.devices.write().unwrap().remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Device>> {
+        self.devices.read().unwrap().get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.devices.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn devices(&self) -> Vec<(String, Arc<Device>)> {
+        self.devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, device)| (name.clone(), device.clone()))
+            .collect()
+    }
+
+    pub fn ptz(&self, name: &str) -> PtzHandle {
+        PtzHandle {
+            name: name.to_string(),
+            device: self.get(name),
+        }
+    }
+
+    /// Run `f` against every device concurrently and collect the results.
+    pub async fn broadcast<F, Fut, T>(&self, f: F) -> Vec<(String, Result<T, DeviceError>)>
+    where
+        F: Fn(Arc<Device>) -> Fut,
+        Fut: Future<Output = Result<T, DeviceError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pending: Vec<_> = self
+            .devices()
+            .into_iter()
+            .map(|(name, device)| (name, tokio::spawn(f(device))))
+            .collect();
+
+        let mut results = vec![];
+        for (name, handle) in pending {
+            let result = handle
+                .await
+                .unwrap_or_else(|e| Err(DeviceError::TaskFailed(e.to_string())));
+            results.push((name, result));
+        }
+        results
+    }
+
+    pub async fn stop_all(&self) -> Vec<(String, Result<(), DeviceError>)> {
+        self.broadcast(|device| async move {
+            ptz::send_stop_ptz(&device, ptz::StopAxes::All, None).await
+        })
+        .await
+    }
+
+    pub async fn goto_home_all(&self) -> Vec<(String, Result<(), DeviceError>)> {
+        self.broadcast(|device| async move { ptz::goto_home(&device, None).await })
+            .await
+    }
+}
+
+/// PTZ commands for a single named device of a `DeviceManager`.
+pub struct PtzHandle {
+    name: String,
+    device: Option<Arc<Device>>,
+}
+
+impl PtzHandle {
+    fn device(&self) -> Result<&Device, DeviceError> {
+        self.device
+            .as_deref()
+            .ok_or_else(|| DeviceError::UnknownDevice(self.name.clone()))
+    }
+
+    pub async fn continuous(&self, pan: f64, tilt: f64, zoom: f64) -> Result<(), DeviceError> {
+        let device = self.device()?;
+        ptz::send_continuous_ptz(
+            device,
+            pan,
+            tilt,
+            zoom,
+            device.continuous_timeout(),
+            false,
+            None,
+        )
+        .await
+    }
+
+    pub async fn relative(&self, pan: f64, tilt: f64, zoom: f64) -> Result<(), DeviceError> {
+        ptz::send_relative_ptz(self.device()?, pan, tilt, zoom, None, None).await
+    }
+
+    pub async fn absolute(
+        &self,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        speed: Option<schema::onvif::Ptzspeed>,
+    ) -> Result<(), DeviceError> {
+        ptz::send_absolute_ptz(self.device()?, pan, tilt, zoom, speed, None).await
+    }
+
+    pub async fn stop(&self) -> Result<(), DeviceError> {
+        ptz::send_stop_ptz(self.device()?, ptz::StopAxes::All, None).await
+    }
+
+    pub async fn goto_home(
+        &self,
+        speed: Option<schema::onvif::Ptzspeed>,
+    ) -> Result<(), DeviceError> {
+        ptz::goto_home(self.device()?, speed).await
+    }
+
+    pub async fn goto_preset(
+        &self,
+        preset_token: schema::onvif::ReferenceToken,
+        speed: Option<schema::onvif::Ptzspeed>,
+    ) -> Result<(), DeviceError> {
+        presets::goto_preset(self.device()?, preset_token, speed).await
+    }
+
+    pub async fn status(&self) -> Result<ptz::PtzStatus, DeviceError> {
+        ptz::get_ptz_status(self.device()?).await
+    }
+}
@@ -0,0 +1,377 @@
+use onvif::schema;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::device::{Device, Service};
+use crate::error::{tls_failure, DeviceError};
+
+pub async fn get_profiles(device: &Device) -> Result<Vec<schema::onvif::Profile>, DeviceError> {
+    let media_client = &*device.media_client()?;
+    Ok(device
+        .retry_policy
+        .run("get_profiles", true, || async move {
+            schema::media::get_profiles(media_client, &Default::default())
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .profiles)
+}
+
+/// The first profile, `NoProfiles` on cameras that have none yet, e.g. after
+/// a factory reset.
+pub async fn get_profile(device: &Device) -> Result<schema::onvif::Profile, DeviceError> {
+    get_profiles(device)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(DeviceError::NoProfiles)
+}
+
+pub async fn get_profile_by_token(
+    device: &Device,
+    token: &schema::onvif::ReferenceToken,
+) -> Result<schema::onvif::Profile, DeviceError> {
+    let profiles = get_profiles(device).await?;
+    let available = profile_names(&profiles);
+    profiles
+        .into_iter()
+        .find(|profile| profile.token.0 == token.0)
+        .ok_or_else(|| DeviceError::UnknownProfile {
+            requested: token.0.clone(),
+            available,
+        })
+}
+
+pub async fn profile_token_by_name(
+    device: &Device,
+    name: &str,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    let profiles = get_profiles(device).await?;
+    match profiles.iter().find(|profile| profile.name.0 == name) {
+        Some(profile) => Ok(schema::onvif::ReferenceToken(profile.token.0.clone())),
+        None => Err(DeviceError::UnknownProfile {
+            requested: name.to_string(),
+            available: profile_names(&profiles),
+        }),
+    }
+}
+
+pub async fn profile_token_by_index(
+    device: &Device,
+    idx: usize,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    let profiles = get_profiles(device).await?;
+    match profiles.get(idx) {
+        Some(profile) => Ok(schema::onvif::ReferenceToken(profile.token.0.clone())),
+        None => Err(DeviceError::UnknownProfile {
+            requested: format!("#{}", idx),
+            available: profile_names(&profiles),
+        }),
+    }
+}
+
+fn profile_names(profiles: &[schema::onvif::Profile]) -> Vec<String> {
+    profiles
+        .iter()
+        .map(|profile| profile.name.0.clone())
+        .collect()
+}
+
+/// `profile` if given, the first profile otherwise.
+pub(crate) async fn resolve_profile_token(
+    device: &Device,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    match profile {
+        Some(profile_token) => Ok(profile_token),
+        None => get_profile_token(device).await,
+    }
+}
+
+/// Token of the first profile. Only the first call asks the camera, use
+/// `Device::refresh_profiles` when the profile set changes.
+pub async fn get_profile_token(
+    device: &Device,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    if let Some(ref token) = *device.profile_token.lock().unwrap() {
+        return Ok(schema::onvif::ReferenceToken(token.clone()));
+    }
+
+    let token = match media2_profile_token(device).await {
+        Some(token) => token,
+        None => get_profile(device).await?.token.0,
+    };
+    *device.profile_token.lock().unwrap() = Some(token.clone());
+    Ok(schema::onvif::ReferenceToken(token))
+}
+
+/// Profiles from the Media2 service, which current Profile T cameras offer
+/// instead of ver10 media.
+pub async fn get_media2_profiles(
+    device: &Device,
+) -> Result<Vec<schema::media2::MediaProfile>, DeviceError> {
+    let media2 = &*device.media2_client()?;
+    let request = &schema::media2::GetProfiles {
+        token: None,
+        _type: vec!["All".to_string()],
+    };
+    Ok(device
+        .retry_policy
+        .run("media2_get_profiles", true, || async move {
+            schema::media2::get_profiles(media2, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .profiles)
+}
+
+/// First profile from the Media2 service, which some Profile T cameras
+/// answer correctly while their ver10 media service returns no profiles.
+async fn media2_profile_token(device: &Device) -> Option<String> {
+    device.media2_client().ok()?;
+    match get_media2_profiles(device).await {
+        Ok(profiles) => profiles.into_iter().next().map(|profile| profile.token.0),
+        Err(e) => {
+            warn!("media2 GetProfiles failed, trying media: {}", e);
+            None
+        }
+    }
+}
+
+impl Device {
+    /// The ver10 media service. Fixed devices without one still answer
+    /// device management calls, so this is only checked when it is needed.
+    pub fn media_client(&self) -> Result<Service, DeviceError> {
+        self.service("media", |connection| connection.media.as_ref())
+    }
+
+    pub fn media2_client(&self) -> Result<Service, DeviceError> {
+        self.service("media2", |connection| connection.media2.as_ref())
+    }
+
+    /// Forget the cached profile token and velocity ranges and fetch the
+    /// profile again.
+    pub async fn refresh_profiles(&self) -> Result<(), DeviceError> {
+        *self.profile_token.lock().unwrap() = None;
+        self.move_spaces.lock().unwrap().clear();
+        self.emulate_relative.lock().unwrap().clear();
+        get_profile_token(self).await.map(|_| ())
+    }
+}
+
+/// RTSP URI of `profile` or the first profile, from Media2 when the camera
+/// has it.
+pub async fn get_stream_uri(
+    device: &Device,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<Url, DeviceError> {
+    if device.media2_client().is_ok() {
+        return get_media2_stream_uri(device, profile).await;
+    }
+
+    let media_client = &*device.media_client()?;
+    let profile_token = resolve_profile_token(device, profile).await?;
+
+    let response = schema::media::get_stream_uri(
+        media_client,
+        &schema::media::GetStreamUri {
+            stream_setup: schema::onvif::StreamSetup {
+                stream: schema::onvif::StreamType::RtpUnicast,
+                transport: schema::onvif::Transport {
+                    protocol: schema::onvif::TransportProtocol::Rtsp,
+                    tunnel: vec![],
+                },
+            },
+            profile_token,
+        },
+    )
+    .await?;
+
+    let mut uri = Url::parse(&response.media_uri.uri)?;
+    embed_credentials(device, &mut uri);
+    Ok(uri)
+}
+
+pub async fn get_media2_stream_uri(
+    device: &Device,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<Url, DeviceError> {
+    let media2 = &*device.media2_client()?;
+    let profile_token = resolve_profile_token(device, profile).await?;
+
+    let request = &schema::media2::GetStreamUri {
+        protocol: "RTSP".to_string(),
+        profile_token,
+    };
+    let response = device
+        .retry_policy
+        .run("media2_get_stream_uri", true, || async move {
+            schema::media2::get_stream_uri(media2, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?;
+
+    let mut uri = Url::parse(&response.uri)?;
+    embed_credentials(device, &mut uri);
+    Ok(uri)
+}
+
+/// Put the device credentials in the URI's userinfo so tools like ffmpeg
+/// can open it directly.
+fn embed_credentials(device: &Device, uri: &mut Url) {
+    if let Some(creds) = device.credentials() {
+        // Only fails for URIs that cannot carry credentials, leave those as is.
+        let _ = uri.set_username(&creds.username);
+        let _ = uri.set_password(Some(&creds.password));
+    }
+}
+
+pub async fn get_snapshot_uri(
+    device: &Device,
+    profile: Option<schema::onvif::ReferenceToken>,
+    with_credentials: bool,
+) -> Result<Url, DeviceError> {
+    let media_client = &*device.media_client()?;
+    let profile_token = resolve_profile_token(device, profile).await?;
+
+    let response = schema::media::get_snapshot_uri(
+        media_client,
+        &schema::media::GetSnapshotUri { profile_token },
+    )
+    .await?;
+
+    let mut uri = Url::parse(&response.media_uri.uri)?;
+    if with_credentials {
+        embed_credentials(device, &mut uri);
+    }
+    Ok(uri)
+}
+
+fn osd_client(device: &Device) -> Result<Service, DeviceError> {
+    let media_client = device.media_client()?;
+    if !device.supports_osd() {
+        return Err(DeviceError::Unsupported("on-screen display"));
+    }
+    Ok(media_client)
+}
+
+/// On-screen display overlays, of the video source configuration
+/// `config_token` or all of them.
+pub async fn get_osds(
+    device: &Device,
+    config_token: Option<schema::onvif::ReferenceToken>,
+) -> Result<Vec<schema::onvif::Osdconfiguration>, DeviceError> {
+    let media_client = &*osd_client(device)?;
+    let request = &schema::media::GetOsds {
+        configuration_token: config_token,
+    };
+    Ok(device
+        .retry_policy
+        .run("get_osds", true, || async move {
+            schema::media::get_osds(media_client, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .osds)
+}
+
+/// Replace the text of the plain-text overlay `osd_token`, e.g. the camera
+/// name. Date and time overlays are rejected.
+pub async fn set_osd_text(device: &Device, osd_token: &str, text: &str) -> Result<(), DeviceError> {
+    let media_client = &*osd_client(device)?;
+
+    let mut osd = schema::media::get_osd(
+        media_client,
+        &schema::media::GetOsd {
+            osd_token: schema::onvif::ReferenceToken(osd_token.to_string()),
+        },
+    )
+    .await?
+    .osd;
+    match osd.text_string {
+        Some(ref mut text_string) if text_string._type == "Plain" => {
+            text_string.plain_text = Some(text.to_string());
+        }
+        _ => {
+            return Err(DeviceError::InvalidArgument(format!(
+                "OSD {} is not a plain text overlay",
+                osd_token
+            )))
+        }
+    }
+
+    info!("set OSD {} text: {}", osd_token, text);
+    let response = schema::media::set_osd(media_client, &schema::media::SetOsd { osd }).await;
+    device.track(response.map_err(DeviceError::from))?;
+
+    Ok(())
+}
+
+/// Download a JPEG snapshot of the first profile. Credentials are only sent
+/// if the camera asks for them, with Digest if it challenges for it and
+/// Basic otherwise.
+pub async fn fetch_snapshot(device: &Device) -> Result<Vec<u8>, DeviceError> {
+    let uri = get_snapshot_uri(device, None, false).await?;
+    let client = device.http_client()?;
+    let get = || client.get(uri.clone()).timeout(device.timeout());
+
+    let mut response = get().send().await.map_err(http_error)?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        if let Some(creds) = device.credentials() {
+            let request = match digest_challenge(&response) {
+                Some(mut prompt) => {
+                    let context = digest_auth::AuthContext::new(
+                        creds.username.as_str(),
+                        creds.password.as_str(),
+                        &uri[url::Position::BeforePath..],
+                    );
+                    let answer = prompt
+                        .respond(&context)
+                        .map_err(|e| DeviceError::Http(e.to_string()))?;
+                    get().header(reqwest::header::AUTHORIZATION, answer.to_header_string())
+                }
+                None => get().basic_auth(&creds.username, Some(&creds.password)),
+            };
+            response = request.send().await.map_err(http_error)?;
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(DeviceError::AuthenticationFailed {
+                    username: creds.username.clone(),
+                });
+            }
+        }
+    }
+
+    let response = response.error_for_status().map_err(http_error)?;
+    let bytes = response.bytes().await.map_err(http_error)?;
+    Ok(bytes.to_vec())
+}
+
+/// The Digest challenge among the `WWW-Authenticate` headers of a 401.
+fn digest_challenge(response: &reqwest::Response) -> Option<digest_auth::WwwAuthenticateHeader> {
+    response
+        .headers()
+        .get_all(reqwest::header::WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| {
+            value
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("digest")
+        })
+        .and_then(|value| digest_auth::parse(value).ok())
+}
+
+fn http_error(e: reqwest::Error) -> DeviceError {
+    if e.is_timeout() {
+        DeviceError::Timeout
+    } else if let Some(tls) = tls_failure(&e) {
+        DeviceError::Tls(tls.to_string())
+    } else {
+        DeviceError::Http(e.to_string())
+    }
+}
@@ -0,0 +1,160 @@
+use onvif::schema;
+use serde::Serialize;
+use tracing::info;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::media::get_profile_token;
+
+/// A preset of the cached profile. Cameras may leave out the name or the
+/// position, which are `None` then.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Preset {
+    pub token: String,
+    pub name: Option<String>,
+    pub pan: Option<f64>,
+    pub tilt: Option<f64>,
+    pub zoom: Option<f64>,
+}
+
+impl From<schema::onvif::Ptzpreset> for Preset {
+    fn from(preset: schema::onvif::Ptzpreset) -> Self {
+        let position = preset.ptz_position.as_ref();
+        let pan_tilt = position.and_then(|p| p.pan_tilt.as_ref());
+
+        Self {
+            token: preset.token.map(|token| token.0).unwrap_or_default(),
+            name: preset.name.map(|name| name.0),
+            pan: pan_tilt.map(|v| v.x),
+            tilt: pan_tilt.map(|v| v.y),
+            zoom: position.and_then(|p| p.zoom.as_ref()).map(|v| v.x),
+        }
+    }
+}
+
+/// One line per preset for text output: token, name and whatever position
+/// the camera reported.
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.token)?;
+        if let Some(ref name) = self.name {
+            write!(f, " {:?}", name)?;
+        }
+        for (axis, value) in [("pan", self.pan), ("tilt", self.tilt), ("zoom", self.zoom)] {
+            if let Some(value) = value {
+                write!(f, " {}={:.3}", axis, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Presets without a token can't be addressed and are left out.
+pub async fn list_presets(device: &Device) -> Result<Vec<Preset>, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_presets() {
+        return Err(DeviceError::Unsupported("presets"));
+    }
+    let request = &schema::ptz::GetPresets {
+        profile_token: get_profile_token(device).await?,
+    };
+    let presets = device
+        .retry_policy
+        .run("get_presets", true, || async move {
+            schema::ptz::get_presets(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .preset;
+
+    Ok(presets
+        .into_iter()
+        .filter(|preset| preset.token.is_some())
+        .map(Preset::from)
+        .collect())
+}
+
+pub async fn goto_preset(
+    device: &Device,
+    preset_token: schema::onvif::ReferenceToken,
+    speed: Option<schema::onvif::Ptzspeed>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_presets() {
+        return Err(DeviceError::Unsupported("presets"));
+    }
+    let _turn = device.ptz_queue.turn().await;
+    info!("goto preset: {}", preset_token.0);
+    let request = &schema::ptz::GotoPreset {
+        profile_token: get_profile_token(device).await?,
+        preset_token,
+        speed,
+    };
+    let response = device
+        .retry_policy
+        .run("goto_preset", true, || async move {
+            schema::ptz::goto_preset(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    device.track(response)?;
+
+    Ok(())
+}
+
+/// Save the current position as preset `name`. An existing preset with the
+/// same name is overwritten rather than duplicated.
+pub async fn set_preset(
+    device: &Device,
+    name: &str,
+) -> Result<schema::onvif::ReferenceToken, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_presets() {
+        return Err(DeviceError::Unsupported("presets"));
+    }
+    let existing = list_presets(device)
+        .await?
+        .into_iter()
+        .find(|preset| preset.name.as_deref() == Some(name))
+        .map(|preset| schema::onvif::ReferenceToken(preset.token));
+
+    info!("set preset: {} (overwriting {:?})", name, existing);
+    let response = schema::ptz::set_preset(
+        ptz,
+        &schema::ptz::SetPreset {
+            profile_token: get_profile_token(device).await?,
+            preset_name: Some(name.to_string()),
+            preset_token: existing,
+        },
+    )
+    .await
+    .map_err(|e| match DeviceError::from(e) {
+        e if e.is_fault("TooManyPresets") => DeviceError::TooManyPresets,
+        e => e,
+    })?;
+
+    Ok(response.preset_token)
+}
+
+pub async fn remove_preset(
+    device: &Device,
+    preset_token: schema::onvif::ReferenceToken,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_presets() {
+        return Err(DeviceError::Unsupported("presets"));
+    }
+    info!("remove preset: {}", preset_token.0);
+    schema::ptz::remove_preset(
+        ptz,
+        &schema::ptz::RemovePreset {
+            profile_token: get_profile_token(device).await?,
+            preset_token,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,1969 @@
+use onvif::schema;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::media::{
+    get_media2_profiles, get_profile, get_profile_by_token, get_profile_token,
+    resolve_profile_token,
+};
+use crate::quirks;
+use crate::trace::ServiceClient;
+
+/// A coordinate space and its ranges. `y` is `None` for zoom spaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Space {
+    pub uri: String,
+    pub x: (f64, f64),
+    pub y: Option<(f64, f64)>,
+}
+
+/// Coordinate spaces a PTZ node supports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PtzSpaces {
+    pub absolute_pan_tilt_position: Vec<Space>,
+    pub absolute_zoom_position: Vec<Space>,
+    pub relative_pan_tilt_translation: Vec<Space>,
+    pub relative_zoom_translation: Vec<Space>,
+    pub continuous_pan_tilt_velocity: Vec<Space>,
+    pub continuous_zoom_velocity: Vec<Space>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PtzNodeInfo {
+    pub token: String,
+    pub name: Option<String>,
+    pub spaces: PtzSpaces,
+    pub maximum_number_of_presets: i32,
+    pub home_supported: bool,
+    /// 0 if the node has no native preset tours.
+    pub maximum_number_of_preset_tours: i32,
+    /// Whether the node can point at a geographic location.
+    pub geo_move: bool,
+    /// Commands for `auxiliary::send_auxiliary_command`, e.g. `tt:Wiper|On`.
+    pub auxiliary_commands: Vec<String>,
+}
+
+impl PtzNodeInfo {
+    pub fn supports_absolute_move(&self) -> bool {
+        !self.spaces.absolute_pan_tilt_position.is_empty()
+            || !self.spaces.absolute_zoom_position.is_empty()
+    }
+
+    pub fn supports_relative_move(&self) -> bool {
+        !self.spaces.relative_pan_tilt_translation.is_empty()
+            || !self.spaces.relative_zoom_translation.is_empty()
+    }
+
+    pub fn supports_continuous_move(&self) -> bool {
+        !self.spaces.continuous_pan_tilt_velocity.is_empty()
+            || !self.spaces.continuous_zoom_velocity.is_empty()
+    }
+}
+
+impl From<&schema::onvif::Ptznode> for PtzNodeInfo {
+    fn from(node: &schema::onvif::Ptznode) -> Self {
+        let spaces = &node.supported_ptz_spaces;
+        let spaces_2d = |spaces: &[schema::onvif::Space2DDescription]| {
+            spaces
+                .iter()
+                .map(|space| Space {
+                    uri: space.uri.clone(),
+                    x: (space.x_range.min, space.x_range.max),
+                    y: Some((space.y_range.min, space.y_range.max)),
+                })
+                .collect()
+        };
+        let spaces_1d = |spaces: &[schema::onvif::Space1DDescription]| {
+            spaces
+                .iter()
+                .map(|space| Space {
+                    uri: space.uri.clone(),
+                    x: (space.x_range.min, space.x_range.max),
+                    y: None,
+                })
+                .collect()
+        };
+
+        Self {
+            token: node.token.0.clone(),
+            name: node.name.as_ref().map(|name| name.0.clone()),
+            spaces: PtzSpaces {
+                absolute_pan_tilt_position: spaces_2d(&spaces.absolute_pan_tilt_position_space),
+                absolute_zoom_position: spaces_1d(&spaces.absolute_zoom_position_space),
+                relative_pan_tilt_translation: spaces_2d(
+                    &spaces.relative_pan_tilt_translation_space,
+                ),
+                relative_zoom_translation: spaces_1d(&spaces.relative_zoom_translation_space),
+                continuous_pan_tilt_velocity: spaces_2d(&spaces.continuous_pan_tilt_velocity_space),
+                continuous_zoom_velocity: spaces_1d(&spaces.continuous_zoom_velocity_space),
+            },
+            maximum_number_of_presets: node.maximum_number_of_presets,
+            home_supported: node.home_supported,
+            maximum_number_of_preset_tours: node
+                .extension
+                .as_ref()
+                .and_then(|extension| extension.supported_preset_tour.as_ref())
+                .map_or(0, |tours| tours.maximum_number_of_preset_tours),
+            geo_move: node.geo_move.unwrap_or(false),
+            auxiliary_commands: node
+                .auxiliary_commands
+                .iter()
+                .map(|command| command.0.clone())
+                .collect(),
+        }
+    }
+}
+
+pub async fn get_ptz_nodes(device: &Device) -> Result<Vec<schema::onvif::Ptznode>, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let request = &schema::ptz::GetNodes {};
+    Ok(device
+        .retry_policy
+        .run("get_nodes", true, || async move {
+            schema::ptz::get_nodes(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .ptz_node)
+}
+
+pub async fn get_ptz_node_by_token(
+    device: &Device,
+    node_token: schema::onvif::ReferenceToken,
+) -> Result<schema::onvif::Ptznode, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    Ok(
+        schema::ptz::get_node(ptz, &schema::ptz::GetNode { node_token })
+            .await?
+            .ptz_node,
+    )
+}
+
+/// The PTZ node of the first profile's PTZ configuration.
+pub async fn get_ptz_node(device: &Device) -> Result<schema::onvif::Ptznode, DeviceError> {
+    let node_token = match get_profile(device).await {
+        Ok(profile) => profile.ptz_configuration.map(|config| config.node_token.0),
+        // Media2-only cameras keep the PTZ configuration in the profile's
+        // configuration set.
+        Err(DeviceError::MissingService("media")) => get_media2_profiles(device)
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|profile| profile.configurations)
+            .and_then(|configurations| configurations.ptz)
+            .map(|config| config.node_token.0),
+        Err(e) => return Err(e),
+    };
+
+    find_ptz_node(device, node_token).await
+}
+
+/// The PTZ node of `profile_token`'s PTZ configuration.
+pub async fn get_profile_ptz_node(
+    device: &Device,
+    profile_token: &schema::onvif::ReferenceToken,
+) -> Result<schema::onvif::Ptznode, DeviceError> {
+    let node_token = match get_profile_by_token(device, profile_token).await {
+        Ok(profile) => profile.ptz_configuration.map(|config| config.node_token.0),
+        Err(DeviceError::MissingService("media")) => get_media2_profiles(device)
+            .await?
+            .into_iter()
+            .find(|profile| profile.token.0 == profile_token.0)
+            .and_then(|profile| profile.configurations)
+            .and_then(|configurations| configurations.ptz)
+            .map(|config| config.node_token.0),
+        Err(e) => return Err(e),
+    };
+
+    find_ptz_node(device, node_token).await
+}
+
+/// The node `node_token`, or the first one if the profile names none.
+async fn find_ptz_node(
+    device: &Device,
+    node_token: Option<String>,
+) -> Result<schema::onvif::Ptznode, DeviceError> {
+    get_ptz_nodes(device)
+        .await?
+        .into_iter()
+        .find(|node| {
+            node_token
+                .as_ref()
+                .map_or(true, |token| &node.token.0 == token)
+        })
+        .ok_or(DeviceError::NoPtzNode)
+}
+
+pub async fn get_ptz_configuration(
+    device: &Device,
+    token: schema::onvif::ReferenceToken,
+) -> Result<schema::onvif::Ptzconfiguration, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let request = &schema::ptz::GetConfiguration {
+        ptz_configuration_token: token,
+    };
+    Ok(device
+        .retry_policy
+        .run("get_configuration", true, || async move {
+            schema::ptz::get_configuration(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .ptz_configuration)
+}
+
+/// Pan and tilt limits of a configuration as (min, max), `None` if it sets
+/// none.
+pub fn pan_tilt_limits(
+    config: &schema::onvif::Ptzconfiguration,
+) -> Option<((f64, f64), (f64, f64))> {
+    config.pan_tilt_limits.as_ref().map(|limits| {
+        let range = &limits.range;
+        (
+            (range.x_range.min, range.x_range.max),
+            (range.y_range.min, range.y_range.max),
+        )
+    })
+}
+
+pub fn zoom_limits(config: &schema::onvif::Ptzconfiguration) -> Option<(f64, f64)> {
+    config
+        .zoom_limits
+        .as_ref()
+        .map(|limits| (limits.range.x_range.min, limits.range.x_range.max))
+}
+
+/// Default pan, tilt and zoom speeds of a configuration, each `None` if it
+/// sets none.
+pub fn default_speed(
+    config: &schema::onvif::Ptzconfiguration,
+) -> (Option<f64>, Option<f64>, Option<f64>) {
+    let speed = config.default_ptz_speed.as_ref();
+    let pan_tilt = speed.and_then(|speed| speed.pan_tilt.as_ref());
+    (
+        pan_tilt.map(|v| v.x),
+        pan_tilt.map(|v| v.y),
+        speed.and_then(|speed| speed.zoom.as_ref()).map(|v| v.x),
+    )
+}
+
+/// Spaces from GetConfigurationOptions for the profile's PTZ configuration,
+/// `None` if the profile has no PTZ configuration.
+async fn get_configuration_spaces(
+    device: &Device,
+    profile: &schema::onvif::Profile,
+) -> Result<Option<schema::onvif::Ptzspaces>, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let configuration_token = match profile.ptz_configuration {
+        Some(ref config) => schema::onvif::ReferenceToken(config.token.0.clone()),
+        None => return Ok(None),
+    };
+
+    let request = &schema::ptz::GetConfigurationOptions {
+        configuration_token,
+    };
+    Ok(Some(
+        device
+            .retry_policy
+            .run("get_configuration_options", true, || async move {
+                schema::ptz::get_configuration_options(ptz, request)
+                    .await
+                    .map_err(DeviceError::from)
+            })
+            .await?
+            .ptz_configuration_options
+            .spaces,
+    ))
+}
+
+/// The first space of one kind advertised for a profile, and the ranges
+/// values in it must fall within. `None` URIs leave the choice to the camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveSpace {
+    pub pan_tilt: Option<String>,
+    pub zoom: Option<String>,
+    pub pan: (f64, f64),
+    pub tilt: (f64, f64),
+    pub zoom_range: (f64, f64),
+}
+
+impl MoveSpace {
+    /// The ranges of a generic space.
+    fn generic(zoom_range: (f64, f64)) -> Self {
+        Self {
+            pan_tilt: None,
+            zoom: None,
+            pan: (-1.0, 1.0),
+            tilt: (-1.0, 1.0),
+            zoom_range,
+        }
+    }
+
+    fn advertised(
+        pan_tilt: &[schema::onvif::Space2DDescription],
+        zoom: &[schema::onvif::Space1DDescription],
+        zoom_range: (f64, f64),
+    ) -> Self {
+        let mut space = Self::generic(zoom_range);
+        if let Some(pan_tilt) = pan_tilt.first() {
+            space.pan_tilt = Some(pan_tilt.uri.clone());
+            space.pan = (pan_tilt.x_range.min, pan_tilt.x_range.max);
+            space.tilt = (pan_tilt.y_range.min, pan_tilt.y_range.max);
+        }
+        if let Some(zoom) = zoom.first() {
+            space.zoom = Some(zoom.uri.clone());
+            space.zoom_range = (zoom.x_range.min, zoom.x_range.max);
+        }
+        space
+    }
+
+    /// Clamp each value to its range, or with `strict` reject values outside
+    /// it. NaN and infinite values are always rejected.
+    pub fn clamp(
+        &self,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+        strict: bool,
+    ) -> Result<(f64, f64, f64), DeviceError> {
+        Ok((
+            clamp_axis("pan", pan, self.pan, strict)?,
+            clamp_axis("tilt", tilt, self.tilt, strict)?,
+            clamp_axis("zoom", zoom, self.zoom_range, strict)?,
+        ))
+    }
+
+    /// Turn a move as the operator sees it into one for the camera. Inverted
+    /// axes are mirrored around the middle of their range, which is a plain
+    /// sign flip for velocities and translations.
+    pub fn orient(
+        &self,
+        orientation: Orientation,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+    ) -> (f64, f64, f64) {
+        let mirror = |value: f64, (min, max): (f64, f64), invert: bool| {
+            if invert {
+                min + max - value
+            } else {
+                value
+            }
+        };
+        let (pan, tilt) = if orientation.swap_pan_tilt {
+            (tilt, pan)
+        } else {
+            (pan, tilt)
+        };
+
+        (
+            mirror(pan, self.pan, orientation.invert_pan),
+            mirror(tilt, self.tilt, orientation.invert_tilt),
+            mirror(zoom, self.zoom_range, orientation.invert_zoom),
+        )
+    }
+}
+
+/// How a camera is mounted, applied by `MoveSpace::orient` to every move
+/// so recentering and direct moves agree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub invert_pan: bool,
+    /// For cameras mounted upside down.
+    pub invert_tilt: bool,
+    pub invert_zoom: bool,
+    /// Pan and tilt are exchanged before inverting, e.g. for dewarped
+    /// fisheye views.
+    pub swap_pan_tilt: bool,
+}
+
+/// The device's orientation with its model's quirks applied.
+async fn orientation(device: &Device) -> Orientation {
+    quirks::quirks(device).await.orient(device.orientation())
+}
+
+/// Orient and clamp a move for `space`, with the `quirks` the caller
+/// already looked up.
+fn to_camera(
+    device: &Device,
+    quirks: &quirks::Quirks,
+    space: &MoveSpace,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+) -> Result<(f64, f64, f64), DeviceError> {
+    let orientation = quirks.orient(device.orientation());
+    let (pan, tilt, zoom) = space.orient(orientation, pan, tilt, zoom);
+    space.clamp(pan, tilt, zoom, device.strict_ranges())
+}
+
+fn clamp_axis(
+    axis: &str,
+    value: f64,
+    (min, max): (f64, f64),
+    strict: bool,
+) -> Result<f64, DeviceError> {
+    if !value.is_finite() {
+        return Err(DeviceError::InvalidArgument(format!(
+            "{} is {}",
+            axis, value
+        )));
+    }
+    let clamped = value.clamp(min, max);
+    if clamped != value {
+        if strict {
+            return Err(DeviceError::InvalidArgument(format!(
+                "{} {} is outside [{}, {}]",
+                axis, value, min, max
+            )));
+        }
+        warn!(
+            "{} {} out of range [{}, {}], clamped to {}",
+            axis, value, min, max, clamped
+        );
+    }
+    Ok(clamped)
+}
+
+/// Spaces of a profile for each kind of move, from GetConfigurationOptions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveSpaces {
+    pub absolute: MoveSpace,
+    pub relative: MoveSpace,
+    pub continuous: MoveSpace,
+    /// DefaultPTZSpeed of the profile's PTZ configuration, see
+    /// `default_speed`.
+    pub default_speed: (Option<f64>, Option<f64>, Option<f64>),
+}
+
+impl Default for MoveSpaces {
+    fn default() -> Self {
+        Self {
+            absolute: MoveSpace::generic((0.0, 1.0)),
+            relative: MoveSpace::generic((-1.0, 1.0)),
+            continuous: MoveSpace::generic((-1.0, 1.0)),
+            default_speed: (None, None, None),
+        }
+    }
+}
+
+impl From<&schema::onvif::Ptzspaces> for MoveSpaces {
+    fn from(spaces: &schema::onvif::Ptzspaces) -> Self {
+        Self {
+            absolute: MoveSpace::advertised(
+                &spaces.absolute_pan_tilt_position_space,
+                &spaces.absolute_zoom_position_space,
+                (0.0, 1.0),
+            ),
+            relative: MoveSpace::advertised(
+                &spaces.relative_pan_tilt_translation_space,
+                &spaces.relative_zoom_translation_space,
+                (-1.0, 1.0),
+            ),
+            continuous: MoveSpace::advertised(
+                &spaces.continuous_pan_tilt_velocity_space,
+                &spaces.continuous_zoom_velocity_space,
+                (-1.0, 1.0),
+            ),
+            default_speed: (None, None, None),
+        }
+    }
+}
+
+/// Spaces of the profile with `profile_token`, cached per profile.
+pub async fn get_move_spaces(
+    device: &Device,
+    profile_token: &schema::onvif::ReferenceToken,
+) -> Result<MoveSpaces, DeviceError> {
+    if let Some(spaces) = device.move_spaces.lock().unwrap().get(&profile_token.0) {
+        return Ok(spaces.clone());
+    }
+
+    let spaces = match get_profile_by_token(device, profile_token).await {
+        Ok(profile) => {
+            let mut spaces = match get_configuration_spaces(device, &profile).await? {
+                Some(spaces) => MoveSpaces::from(&spaces),
+                None => MoveSpaces::default(),
+            };
+            if let Some(ref config) = profile.ptz_configuration {
+                spaces.default_speed = default_speed(config);
+            }
+            spaces
+        }
+        // Media2-only cameras have no ver10 profile to read the spaces from.
+        Err(DeviceError::MissingService("media")) => MoveSpaces::default(),
+        Err(e) => return Err(e),
+    };
+
+    device
+        .move_spaces
+        .lock()
+        .unwrap()
+        .insert(profile_token.0.clone(), spaces.clone());
+    Ok(spaces)
+}
+
+/// `duration` as an xs:duration, all in seconds, e.g. `PT150.5S`.
+pub(crate) fn xsd_duration(duration: std::time::Duration) -> xsd_types::types::duration::Duration {
+    xsd_types::types::duration::Duration {
+        seconds: duration.as_secs_f64(),
+        ..Default::default()
+    }
+}
+
+/// An xs:duration as a `Duration`. Years and months are left out, no camera
+/// dwells or times out that long.
+pub(crate) fn std_duration(duration: &xsd_types::types::duration::Duration) -> std::time::Duration {
+    let seconds = duration.days as f64 * 86400.0
+        + duration.hours as f64 * 3600.0
+        + duration.minutes as f64 * 60.0
+        + duration.seconds;
+    std::time::Duration::from_secs_f64(seconds.max(0.0))
+}
+
+/// Scale a pan/tilt vector longer than 1 back onto the unit circle, so
+/// diagonals are no faster than straight moves.
+fn normalize_pan_tilt(pan: f64, tilt: f64) -> (f64, f64) {
+    let magnitude = pan.hypot(tilt);
+    if magnitude > 1.0 {
+        (pan / magnitude, tilt / magnitude)
+    } else {
+        (pan, tilt)
+    }
+}
+
+/// The camera stops by itself after `timeout`, `None` leaves that to its
+/// configured default. `Device::continuous_timeout` is the usual choice.
+/// With `normalize`, pan and tilt may be a joystick vector of any length.
+pub async fn send_continuous_ptz(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    timeout: Option<std::time::Duration>,
+    normalize: bool,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_continuous_move() {
+        return Err(DeviceError::Unsupported("continuous move"));
+    }
+    let _turn = match device.ptz_queue.continuous_turn().await {
+        Some(turn) => turn,
+        // Superseded by a later move or a stop while it was waiting.
+        None => return Ok(()),
+    };
+    let profile_token = resolve_profile_token(device, profile).await?;
+    let spaces = get_move_spaces(device, &profile_token).await?.continuous;
+    let (pan, tilt) = if normalize {
+        normalize_pan_tilt(pan, tilt)
+    } else {
+        (pan, tilt)
+    };
+    let quirks = quirks::quirks(device).await;
+    let (pan, tilt, zoom) = quirks.scale_velocity(pan, tilt, zoom);
+    let (pan, tilt, zoom) = to_camera(device, &quirks, &spaces, pan, tilt, zoom)?;
+
+    info!("continuous pan: {}, tilt: {}, zoom: {}", pan, tilt, zoom);
+    debug!(
+        "continuous spaces: {:?}, {:?}",
+        spaces.pan_tilt, spaces.zoom
+    );
+    let pan_tilt = Some(schema::common::Vector2D {
+        x: pan,
+        y: tilt,
+        space: spaces.pan_tilt,
+    });
+    let zoom = Some(schema::common::Vector1D {
+        x: zoom,
+        space: spaces.zoom,
+    });
+    let velocity = schema::onvif::Ptzspeed { pan_tilt, zoom };
+
+    let request = &schema::ptz::ContinuousMove {
+        profile_token,
+        velocity,
+        timeout: quirks.timeout(timeout),
+    };
+    let result = device
+        .retry_policy
+        .run("continuous_move", true, || async move {
+            schema::ptz::continuous_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    device.track(result)?;
+
+    Ok(())
+}
+
+/// Sends Stop from a new task if dropped while armed, so a cancelled
+/// `continuous_move_for` doesn't leave the camera moving.
+struct StopGuard {
+    stop: Option<(ServiceClient, schema::onvif::ReferenceToken)>,
+    stop_timeout: std::time::Duration,
+}
+
+impl StopGuard {
+    fn disarm(&mut self) {
+        self.stop = None;
+    }
+}
+
+impl Drop for StopGuard {
+    fn drop(&mut self) {
+        let (ptz, profile_token) = match self.stop.take() {
+            Some(stop) => stop,
+            None => return,
+        };
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        let stop_timeout = self.stop_timeout;
+        runtime.spawn(async move {
+            let request = schema::ptz::Stop {
+                profile_token,
+                pan_tilt: Some(true),
+                zoom: Some(true),
+            };
+            match tokio::time::timeout(stop_timeout, schema::ptz::stop(&ptz, &request)).await {
+                Ok(Ok(_)) => debug!("ptz stopped after a cancelled move"),
+                Ok(Err(e)) => warn!("failed to stop after a cancelled move: {}", e),
+                Err(_) => warn!("failed to stop after a cancelled move: timed out"),
+            }
+        });
+    }
+}
+
+/// Opt-in velocity ramps for `continuous_move_for`, for heavy domes that
+/// lurch and overshoot when sent full speed at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveProfile {
+    /// Time to step up from standstill to the requested velocity.
+    pub ramp_up: std::time::Duration,
+    /// Time to step back down before the stop.
+    pub ramp_down: std::time::Duration,
+    /// Longest wait between two velocity steps.
+    pub max_step_interval: std::time::Duration,
+}
+
+impl Default for MoveProfile {
+    fn default() -> Self {
+        Self {
+            ramp_up: std::time::Duration::from_millis(300),
+            ramp_down: std::time::Duration::from_millis(300),
+            max_step_interval: std::time::Duration::from_millis(100),
+        }
+    }
+}
+
+impl MoveProfile {
+    /// Number of steps for a ramp of `ramp`, and the wait after each.
+    fn steps(&self, ramp: std::time::Duration) -> (u32, std::time::Duration) {
+        if ramp.is_zero() {
+            return (0, ramp);
+        }
+        let interval = self
+            .max_step_interval
+            .max(std::time::Duration::from_millis(1));
+        let steps = (ramp.as_secs_f64() / interval.as_secs_f64()).ceil() as u32;
+        (steps, ramp / steps)
+    }
+}
+
+/// How `ramp` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RampEnd {
+    Finished,
+    /// A stop was sent from elsewhere.
+    Stopped,
+    Cancelled,
+}
+
+/// Send the velocity scaled by each fraction of `steps`, waiting as long
+/// as the step says after each. A stop sent meanwhile or `cancel` ends the
+/// move.
+async fn ramp(
+    device: &Device,
+    (pan, tilt, zoom): (f64, f64, f64),
+    steps: impl Iterator<Item = (f64, std::time::Duration)>,
+    timeout: std::time::Duration,
+    profile_token: &schema::onvif::ReferenceToken,
+    stops: u64,
+    cancel: &CancellationToken,
+) -> Result<RampEnd, DeviceError> {
+    for (fraction, wait) in steps {
+        if device.ptz_queue.stops() != stops {
+            return Ok(RampEnd::Stopped);
+        }
+        send_continuous_ptz(
+            device,
+            pan * fraction,
+            tilt * fraction,
+            zoom * fraction,
+            Some(timeout),
+            false,
+            Some(profile_token.clone()),
+        )
+        .await?;
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(RampEnd::Cancelled),
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+    if device.ptz_queue.stops() == stops {
+        Ok(RampEnd::Finished)
+    } else {
+        Ok(RampEnd::Stopped)
+    }
+}
+
+/// Move at the given velocity for `duration`, then stop. If the returned
+/// future is dropped before that, the camera is still told to stop.
+///
+/// With a `MoveProfile` on the device the velocity is ramped up and down,
+/// and the time at full velocity shortened so the camera covers about the
+/// same distance. A stop from elsewhere ends the ramp where it is.
+pub async fn continuous_move_for(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    duration: std::time::Duration,
+) -> Result<(), DeviceError> {
+    let never = CancellationToken::new();
+    continuous_move_until(device, pan, tilt, zoom, duration, &never)
+        .await
+        .map(|_| ())
+}
+
+/// `continuous_move_for`, ended early with a stop once `cancel` fires.
+/// Returns `false` if it was.
+pub async fn continuous_move_until(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    duration: std::time::Duration,
+    cancel: &CancellationToken,
+) -> Result<bool, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let profile_token = get_profile_token(device).await?;
+
+    let mut guard = StopGuard {
+        stop: Some((ptz.clone(), profile_token.clone())),
+        stop_timeout: device.stop_timeout,
+    };
+    let move_profile = match device.move_profile() {
+        Some(move_profile) => move_profile,
+        None => {
+            // In case the stop below never arrives.
+            let timeout = duration + RECENTER_STOP_MARGIN;
+            send_continuous_ptz(
+                device,
+                pan,
+                tilt,
+                zoom,
+                Some(timeout),
+                false,
+                Some(profile_token.clone()),
+            )
+            .await?;
+            let cancelled = tokio::select! {
+                _ = cancel.cancelled() => true,
+                _ = tokio::time::sleep(duration) => false,
+            };
+
+            guard.disarm();
+            send_stop_ptz(device, StopAxes::All, Some(profile_token)).await?;
+            return Ok(!cancelled);
+        }
+    };
+
+    let (up_steps, up_interval) = move_profile.steps(move_profile.ramp_up);
+    let (down_steps, down_interval) = move_profile.steps(move_profile.ramp_down);
+    // Ramps cover about half the distance they would at full velocity.
+    let hold = duration.saturating_sub((move_profile.ramp_up + move_profile.ramp_down) / 2);
+    let timeout = move_profile.ramp_up + hold + move_profile.ramp_down + RECENTER_STOP_MARGIN;
+    let velocity = (pan, tilt, zoom);
+    let stops = device.ptz_queue.stops();
+
+    let up = (1..up_steps).map(|step| (step as f64 / up_steps as f64, up_interval));
+    let down = (1..down_steps)
+        .rev()
+        .map(|step| (step as f64 / down_steps as f64, down_interval));
+    let steps = up.chain(std::iter::once((1.0, hold))).chain(down);
+    let end = ramp(
+        device,
+        velocity,
+        steps,
+        timeout,
+        &profile_token,
+        stops,
+        cancel,
+    )
+    .await?;
+    guard.disarm();
+    if end == RampEnd::Stopped {
+        debug!("continuous move ramp ended by a stop");
+        return Ok(true);
+    }
+
+    send_stop_ptz(device, StopAxes::All, Some(profile_token)).await?;
+    Ok(end == RampEnd::Finished)
+}
+
+/// Which movements `send_stop_ptz` halts. There is no variant for neither,
+/// which some cameras answer with a fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopAxes {
+    PanTilt,
+    Zoom,
+    All,
+}
+
+impl Default for StopAxes {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl StopAxes {
+    /// The `PanTilt` and `Zoom` flags of a Stop request.
+    fn flags(self) -> (bool, bool) {
+        match self {
+            Self::PanTilt => (true, false),
+            Self::Zoom => (false, true),
+            Self::All => (true, true),
+        }
+    }
+}
+
+impl std::str::FromStr for StopAxes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pan-tilt" => Ok(Self::PanTilt),
+            "zoom" => Ok(Self::Zoom),
+            "all" => Ok(Self::All),
+            _ => Err(format!(
+                "unknown axes {}, expected pan-tilt, zoom or all",
+                s
+            )),
+        }
+    }
+}
+
+pub async fn send_stop_ptz(
+    device: &Device,
+    axes: StopAxes,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    // Not queued, a stop has to reach the camera as soon as possible.
+    device.ptz_queue.jump();
+    let (pan_tilt, zoom) = axes.flags();
+    let request = &schema::ptz::Stop {
+        profile_token: resolve_profile_token(device, profile).await?,
+        pan_tilt: Some(pan_tilt),
+        zoom: Some(zoom),
+    };
+    // Stopping is always safe to repeat, so it retries even if moves do not.
+    let response = device
+        .retry_policy
+        .run("stop", true, || async move {
+            match tokio::time::timeout(device.stop_timeout, schema::ptz::stop(ptz, request)).await {
+                Ok(response) => response.map_err(DeviceError::from),
+                Err(_) => Err(DeviceError::Timeout),
+            }
+        })
+        .await;
+    debug!("ptz stop: {:?}", device.track(response)?);
+
+    Ok(())
+}
+
+/// Whether relative moves can be done as absolute moves: the node has an
+/// absolute pan/tilt space but no relative one, and reports its position.
+fn can_emulate_relative(node: &PtzNodeInfo, device: &Device) -> bool {
+    node.spaces.relative_pan_tilt_translation.is_empty()
+        && !node.spaces.absolute_pan_tilt_position.is_empty()
+        && device
+            .ptz_capabilities()
+            .map_or(true, |caps| caps.status_position)
+}
+
+/// Cached per profile after the first call, asking needs GetProfiles and
+/// GetNodes. Cameras whose node can't be read keep their native relative
+/// moves.
+async fn emulate_relative(device: &Device, profile_token: &schema::onvif::ReferenceToken) -> bool {
+    if let Some(&emulate) = device
+        .emulate_relative
+        .lock()
+        .unwrap()
+        .get(&profile_token.0)
+    {
+        return emulate;
+    }
+
+    let emulate = quirks::quirks(device).await.emulate_relative
+        || match get_profile_ptz_node(device, profile_token).await {
+            Ok(node) => can_emulate_relative(&PtzNodeInfo::from(&node), device),
+            Err(e) => {
+                debug!(
+                    "no PTZ node for {}, not emulating relative moves: {}",
+                    profile_token.0, e
+                );
+                false
+            }
+        };
+    device
+        .emulate_relative
+        .lock()
+        .unwrap()
+        .insert(profile_token.0.clone(), emulate);
+    emulate
+}
+
+/// Wrap `value` into `[min, max)`, for pan on endlessly turning domes.
+fn wrap(value: f64, (min, max): (f64, f64)) -> f64 {
+    if max > min {
+        min + (value - min).rem_euclid(max - min)
+    } else {
+        value
+    }
+}
+
+/// A relative move done as an absolute one from the current position. The
+/// translation is scaled from the generic [-1, 1] onto the absolute ranges.
+/// Zoom is left alone if the camera doesn't report it.
+async fn relative_via_absolute(
+    device: &Device,
+    ptz: &ServiceClient,
+    (pan, tilt, zoom): (f64, f64, f64),
+    speed: Option<(f64, f64, f64)>,
+    profile_token: schema::onvif::ReferenceToken,
+) -> Result<(), DeviceError> {
+    let _turn = device.ptz_queue.turn().await;
+    let move_spaces = get_move_spaces(device, &profile_token).await?;
+    let spaces = &move_spaces.absolute;
+    let quirks = quirks::quirks(device).await;
+    let (pan, tilt, zoom) = to_camera(device, &quirks, &move_spaces.relative, pan, tilt, zoom)?;
+    let speed = match speed {
+        Some((pan, tilt, zoom)) => {
+            let strict = device.strict_ranges();
+            let velocity = &move_spaces.continuous;
+            Some((
+                clamp_axis("pan speed", pan, velocity.pan, strict)?,
+                clamp_axis("tilt speed", tilt, velocity.tilt, strict)?,
+                clamp_axis("zoom speed", zoom, velocity.zoom_range, strict)?,
+            ))
+        }
+        None => None,
+    };
+
+    let position = get_position(device)
+        .await?
+        .ok_or(DeviceError::Unsupported("position in PTZ status"))?;
+    let current = position
+        .pan_tilt
+        .ok_or(DeviceError::Unsupported("pan/tilt position in PTZ status"))?;
+    let half = |(min, max): (f64, f64)| (max - min) / 2.0;
+    let target_pan = current.x + pan * half(spaces.pan);
+    let target_pan = if quirks.pan_wraps {
+        wrap(target_pan, spaces.pan)
+    } else {
+        target_pan.clamp(spaces.pan.0, spaces.pan.1)
+    };
+    let target_tilt = (current.y + tilt * half(spaces.tilt)).clamp(spaces.tilt.0, spaces.tilt.1);
+    let target_zoom = match position.zoom {
+        Some(current) => Some(
+            (current.x + zoom * (spaces.zoom_range.1 - spaces.zoom_range.0))
+                .clamp(spaces.zoom_range.0, spaces.zoom_range.1),
+        ),
+        None if zoom != 0.0 => return Err(DeviceError::Unsupported("zoom position in PTZ status")),
+        None => None,
+    };
+
+    info!(
+        "relative move as absolute pan: {}, tilt: {}, zoom: {:?}",
+        target_pan, target_tilt, target_zoom
+    );
+    let request = &schema::ptz::AbsoluteMove {
+        profile_token,
+        position: schema::onvif::Ptzvector {
+            pan_tilt: Some(schema::common::Vector2D {
+                x: target_pan,
+                y: target_tilt,
+                space: spaces.pan_tilt.clone(),
+            }),
+            zoom: target_zoom.map(|x| schema::common::Vector1D {
+                x,
+                space: spaces.zoom.clone(),
+            }),
+        },
+        speed: speed.map(crate::tours::ptz_speed),
+    };
+    // Not retried: the position was read before the first attempt.
+    let response = device
+        .retry_policy
+        .run("absolute_move", false, || async move {
+            schema::ptz::absolute_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    debug!("ptz emulated relative move: {:?}", device.track(response)?);
+
+    Ok(())
+}
+
+/// `speed` is clamped to the continuous velocity ranges, `None` uses the
+/// default speed of the profile's PTZ configuration. Cameras without
+/// relative moves but with absolute ones and a position in their status get
+/// an absolute move from where they are.
+pub async fn send_relative_ptz(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    speed: Option<(f64, f64, f64)>,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let profile_token = resolve_profile_token(device, profile).await?;
+    if emulate_relative(device, &profile_token).await {
+        return relative_via_absolute(device, ptz, (pan, tilt, zoom), speed, profile_token).await;
+    }
+    if !device.supports_relative_move() {
+        return Err(DeviceError::Unsupported("relative move"));
+    }
+    let _turn = device.ptz_queue.turn().await;
+    let move_spaces = get_move_spaces(device, &profile_token).await?;
+    let spaces = move_spaces.relative;
+    let quirks = quirks::quirks(device).await;
+    let (pan, tilt, zoom) = to_camera(device, &quirks, &spaces, pan, tilt, zoom)?;
+
+    info!("relative pan: {}, tilt: {}, zoom: {}", pan, tilt, zoom);
+    debug!("relative spaces: {:?}, {:?}", spaces.pan_tilt, spaces.zoom);
+    let pan_tilt = Some(schema::common::Vector2D {
+        x: pan,
+        y: tilt,
+        space: spaces.pan_tilt,
+    });
+    let zoom = Some(schema::common::Vector1D {
+        x: zoom,
+        space: spaces.zoom,
+    });
+    let translation = schema::onvif::Ptzvector { pan_tilt, zoom };
+    // Some domes take a missing speed as full speed, so fall back to the
+    // configured default and only leave it out if there is none.
+    let (pan_speed, tilt_speed, zoom_speed) = match speed {
+        Some((pan, tilt, zoom)) => (Some(pan), Some(tilt), Some(zoom)),
+        None => move_spaces.default_speed,
+    };
+    let strict = device.strict_ranges();
+    let velocity = &move_spaces.continuous;
+    let pan_tilt = match (pan_speed, tilt_speed) {
+        (Some(pan), Some(tilt)) => Some(schema::common::Vector2D {
+            x: clamp_axis("pan speed", pan, velocity.pan, strict)?,
+            y: clamp_axis("tilt speed", tilt, velocity.tilt, strict)?,
+            space: None,
+        }),
+        _ => None,
+    };
+    let zoom = match zoom_speed {
+        Some(zoom) => Some(schema::common::Vector1D {
+            x: clamp_axis("zoom speed", zoom, velocity.zoom_range, strict)?,
+            space: None,
+        }),
+        None => None,
+    };
+    debug!("relative speed: {:?}, {:?}", pan_tilt, zoom);
+    let speed = match (pan_tilt, zoom) {
+        (None, None) => None,
+        (pan_tilt, zoom) => Some(schema::onvif::Ptzspeed { pan_tilt, zoom }),
+    };
+
+    let request = &schema::ptz::RelativeMove {
+        profile_token,
+        translation,
+        speed,
+    };
+    let response = device
+        .retry_policy
+        .run("relative_move", false, || async move {
+            schema::ptz::relative_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    debug!("ptz relative move: {:?}", device.track(response)?);
+
+    Ok(())
+}
+
+/// `send_relative_ptz`, then `wait_for_idle` with the default interval and
+/// deadline.
+pub async fn send_relative_ptz_and_wait(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    speed: Option<(f64, f64, f64)>,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<PtzStatus, DeviceError> {
+    send_relative_ptz(device, pan, tilt, zoom, speed, profile).await?;
+    wait_for_idle(device, IDLE_POLL_INTERVAL, IDLE_DEADLINE).await
+}
+
+pub async fn send_absolute_ptz(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    speed: Option<schema::onvif::Ptzspeed>,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_absolute_move() {
+        return Err(DeviceError::Unsupported("absolute move"));
+    }
+    let _turn = device.ptz_queue.turn().await;
+    let profile_token = resolve_profile_token(device, profile).await?;
+    let move_spaces = get_move_spaces(device, &profile_token).await?;
+    let spaces = move_spaces.absolute;
+    let quirks = quirks::quirks(device).await;
+    let (pan, tilt, zoom) = to_camera(device, &quirks, &spaces, pan, tilt, zoom)?;
+    let speed = match speed {
+        // Guessing full speed would be wrong for cameras with a slow
+        // default, so only the configured one is used.
+        None if quirks.absolute_needs_speed => match move_spaces.default_speed {
+            (Some(pan), Some(tilt), zoom) => Some(schema::onvif::Ptzspeed {
+                pan_tilt: Some(schema::common::Vector2D {
+                    x: pan,
+                    y: tilt,
+                    space: None,
+                }),
+                zoom: zoom.map(|x| schema::common::Vector1D { x, space: None }),
+            }),
+            _ => {
+                return Err(DeviceError::Unsupported(
+                    "absolute move without a speed on a camera without a default speed",
+                ))
+            }
+        },
+        speed => speed,
+    };
+
+    info!("absolute pan: {}, tilt: {}, zoom: {}", pan, tilt, zoom);
+    debug!("absolute spaces: {:?}, {:?}", spaces.pan_tilt, spaces.zoom);
+    let pan_tilt = Some(schema::common::Vector2D {
+        x: pan,
+        y: tilt,
+        space: spaces.pan_tilt,
+    });
+    let zoom = Some(schema::common::Vector1D {
+        x: zoom,
+        space: spaces.zoom,
+    });
+    let position = schema::onvif::Ptzvector { pan_tilt, zoom };
+
+    let request = &schema::ptz::AbsoluteMove {
+        profile_token,
+        position,
+        speed,
+    };
+    // Moving to the same position twice is harmless, so this retries.
+    let response = device
+        .retry_policy
+        .run("absolute_move", true, || async move {
+            schema::ptz::absolute_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    debug!("ptz absolute move: {:?}", device.track(response)?);
+
+    Ok(())
+}
+
+/// `send_absolute_ptz`, then `wait_for_idle` with the default interval and
+/// deadline.
+pub async fn send_absolute_ptz_and_wait(
+    device: &Device,
+    pan: f64,
+    tilt: f64,
+    zoom: f64,
+    speed: Option<schema::onvif::Ptzspeed>,
+    profile: Option<schema::onvif::ReferenceToken>,
+) -> Result<PtzStatus, DeviceError> {
+    send_absolute_ptz(device, pan, tilt, zoom, speed, profile).await?;
+    wait_for_idle(device, IDLE_POLL_INTERVAL, IDLE_DEADLINE).await
+}
+
+/// Fail with `Unsupported` if the PTZ node has no zoom space of the kind
+/// `spaces` picks, e.g. on a fixed lens with a pan/tilt base.
+async fn check_zoom(
+    device: &Device,
+    feature: &'static str,
+    spaces: fn(&PtzSpaces) -> &Vec<Space>,
+) -> Result<(), DeviceError> {
+    match get_ptz_node(device).await {
+        Ok(node) if spaces(&PtzNodeInfo::from(&node).spaces).is_empty() => {
+            Err(DeviceError::Unsupported(feature))
+        }
+        Ok(_) => Ok(()),
+        // Media2-only cameras have no ver10 profile to find the node by.
+        Err(DeviceError::MissingService("media")) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Zoom at `velocity`, negative to zoom out, leaving pan and tilt alone.
+pub async fn zoom_continuous(device: &Device, velocity: f64) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    check_zoom(device, "continuous zoom", |spaces| {
+        &spaces.continuous_zoom_velocity
+    })
+    .await?;
+    let _turn = match device.ptz_queue.continuous_turn().await {
+        Some(turn) => turn,
+        // Superseded by a later move or a stop while it was waiting.
+        None => return Ok(()),
+    };
+    let profile_token = resolve_profile_token(device, None).await?;
+    let spaces = get_move_spaces(device, &profile_token).await?.continuous;
+    let quirks = quirks::quirks(device).await;
+    let (_, _, velocity) = quirks.scale_velocity(0.0, 0.0, velocity);
+    let orientation = quirks.orient(device.orientation());
+    let (_, _, velocity) = spaces.orient(orientation, 0.0, 0.0, velocity);
+    let velocity = clamp_axis("zoom", velocity, spaces.zoom_range, device.strict_ranges())?;
+
+    info!("continuous zoom: {}", velocity);
+    let request = &schema::ptz::ContinuousMove {
+        profile_token,
+        velocity: schema::onvif::Ptzspeed {
+            pan_tilt: None,
+            zoom: Some(schema::common::Vector1D {
+                x: velocity,
+                space: spaces.zoom,
+            }),
+        },
+        timeout: quirks.timeout(device.continuous_timeout()),
+    };
+    let response = device
+        .retry_policy
+        .run("continuous_move", true, || async move {
+            schema::ptz::continuous_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    device.track(response)?;
+
+    Ok(())
+}
+
+pub async fn zoom_stop(device: &Device) -> Result<(), DeviceError> {
+    send_stop_ptz(device, StopAxes::Zoom, None).await
+}
+
+/// Zoom to `level`, from 0.0 for the widest to 1.0 for the narrowest view,
+/// mapped onto the advertised absolute zoom range.
+pub async fn zoom_absolute(device: &Device, level: f64) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    check_zoom(device, "absolute zoom", |spaces| {
+        &spaces.absolute_zoom_position
+    })
+    .await?;
+    let _turn = device.ptz_queue.turn().await;
+    let profile_token = resolve_profile_token(device, None).await?;
+    let spaces = get_move_spaces(device, &profile_token).await?.absolute;
+    let level = clamp_axis("zoom level", level, (0.0, 1.0), device.strict_ranges())?;
+    let (min, max) = spaces.zoom_range;
+    let (_, _, zoom) = spaces.orient(
+        orientation(device).await,
+        0.0,
+        0.0,
+        min + level * (max - min),
+    );
+
+    info!("absolute zoom: {} ({})", zoom, level);
+    let request = &schema::ptz::AbsoluteMove {
+        profile_token,
+        position: schema::onvif::Ptzvector {
+            pan_tilt: None,
+            zoom: Some(schema::common::Vector1D {
+                x: zoom,
+                space: spaces.zoom,
+            }),
+        },
+        speed: None,
+    };
+    let response = device
+        .retry_policy
+        .run("absolute_move", true, || async move {
+            schema::ptz::absolute_move(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    device.track(response)?;
+
+    Ok(())
+}
+
+pub async fn goto_home(
+    device: &Device,
+    speed: Option<schema::onvif::Ptzspeed>,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_home() {
+        return Err(DeviceError::Unsupported("home position"));
+    }
+
+    let _turn = device.ptz_queue.turn().await;
+    info!("goto home position");
+    let request = &schema::ptz::GotoHomePosition {
+        profile_token: get_profile_token(device).await?,
+        speed,
+    };
+    let response = device
+        .retry_policy
+        .run("goto_home_position", true, || async move {
+            schema::ptz::goto_home_position(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    device.track(response).map_err(|e| match e {
+        DeviceError::Fault(fault) if fault.has_code("NoHomePosition") => {
+            DeviceError::NoHomePosition(fault.message)
+        }
+        e => e,
+    })?;
+
+    Ok(())
+}
+
+pub async fn set_home(device: &Device) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    if !device.supports_home() {
+        return Err(DeviceError::Unsupported("home position"));
+    }
+
+    info!("set home position");
+    schema::ptz::set_home_position(
+        ptz,
+        &schema::ptz::SetHomePosition {
+            profile_token: get_profile_token(device).await?,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Point the camera at a geographic location. `area_height` and
+/// `area_width`, in metres, ask the camera to zoom so the area fits.
+pub async fn geo_move(
+    device: &Device,
+    lat: f64,
+    lon: f64,
+    elevation: Option<f32>,
+    area_height: Option<f32>,
+    area_width: Option<f32>,
+) -> Result<(), DeviceError> {
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(DeviceError::InvalidArgument(format!(
+            "latitude {} is outside [-90, 90]",
+            lat
+        )));
+    }
+    if !(-180.0..=180.0).contains(&lon) {
+        return Err(DeviceError::InvalidArgument(format!(
+            "longitude {} is outside [-180, 180]",
+            lon
+        )));
+    }
+    let ptz = &*device.ptz_client()?;
+    if !PtzNodeInfo::from(&get_ptz_node(device).await?).geo_move {
+        return Err(DeviceError::Unsupported("geo move"));
+    }
+
+    let _turn = device.ptz_queue.turn().await;
+    info!(
+        "geo move lat: {}, lon: {}, elevation: {:?}",
+        lat, lon, elevation
+    );
+    let response = schema::ptz::geo_move(
+        ptz,
+        &schema::ptz::GeoMove {
+            profile_token: get_profile_token(device).await?,
+            target: schema::onvif::GeoLocation {
+                lat: Some(lat),
+                lon: Some(lon),
+                elevation,
+            },
+            speed: None,
+            area_height,
+            area_width,
+        },
+    )
+    .await;
+    device.track(response.map_err(DeviceError::from))?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoveState {
+    Idle,
+    Moving,
+    Unknown,
+}
+
+impl From<&schema::onvif::MoveStatus> for MoveState {
+    fn from(status: &schema::onvif::MoveStatus) -> Self {
+        match status {
+            schema::onvif::MoveStatus::Idle => Self::Idle,
+            schema::onvif::MoveStatus::Moving => Self::Moving,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Result of GetStatus. Cameras may leave out the position or the move
+/// status, which are `None` then.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PtzStatus {
+    pub pan: Option<f64>,
+    pub tilt: Option<f64>,
+    pub zoom: Option<f64>,
+    pub pan_tilt_move: Option<MoveState>,
+    pub zoom_move: Option<MoveState>,
+    /// Camera time at which the status was taken.
+    pub utc_time: String,
+}
+
+impl PtzStatus {
+    /// `false` if the camera doesn't report its move status at all.
+    pub fn is_idle(&self) -> bool {
+        let idle = |state: Option<MoveState>| matches!(state, None | Some(MoveState::Idle));
+
+        match (self.pan_tilt_move, self.zoom_move) {
+            (None, None) => false,
+            (pan_tilt, zoom) => idle(pan_tilt) && idle(zoom),
+        }
+    }
+}
+
+async fn fetch_ptz_status(device: &Device) -> Result<schema::onvif::Ptzstatus, DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let request = &schema::ptz::GetStatus {
+        profile_token: get_profile_token(device).await?,
+    };
+    let response = device
+        .retry_policy
+        .run("get_status", true, || async move {
+            schema::ptz::get_status(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+    Ok(device.track(response)?.ptz_status)
+}
+
+pub async fn get_ptz_status(device: &Device) -> Result<PtzStatus, DeviceError> {
+    let status = fetch_ptz_status(device).await?;
+
+    let position = status.position.as_ref();
+    let pan_tilt = position.and_then(|p| p.pan_tilt.as_ref());
+
+    Ok(PtzStatus {
+        pan: pan_tilt.map(|v| v.x),
+        tilt: pan_tilt.map(|v| v.y),
+        zoom: position.and_then(|p| p.zoom.as_ref()).map(|v| v.x),
+        pan_tilt_move: status
+            .move_status
+            .as_ref()
+            .and_then(|status| status.pan_tilt.as_ref())
+            .map(MoveState::from),
+        zoom_move: status
+            .move_status
+            .as_ref()
+            .and_then(|status| status.zoom.as_ref())
+            .map(MoveState::from),
+        utc_time: status.utc_time.to_string(),
+    })
+}
+
+/// Where the camera is pointing now, `None` if it doesn't report its position.
+pub async fn get_position(
+    device: &Device,
+) -> Result<Option<schema::onvif::Ptzvector>, DeviceError> {
+    Ok(fetch_ptz_status(device).await?.position)
+}
+
+/// How often the `_and_wait` helpers poll GetStatus.
+pub const IDLE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// How long the `_and_wait` helpers wait for the camera to arrive.
+pub const IDLE_DEADLINE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Position change between polls below which a camera without move status
+/// is taken to have stopped.
+const STABLE_EPSILON: f64 = 1e-3;
+
+fn same_position(a: &PtzStatus, b: &PtzStatus) -> bool {
+    let close = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => (a - b).abs() < STABLE_EPSILON,
+        (None, None) => true,
+        _ => false,
+    };
+
+    (a.pan.is_some() || a.tilt.is_some() || a.zoom.is_some())
+        && close(a.pan, b.pan)
+        && close(a.tilt, b.tilt)
+        && close(a.zoom, b.zoom)
+}
+
+/// Poll GetStatus every `poll_interval` until pan/tilt and zoom are idle and
+/// return the final status. Cameras that don't report a move status are
+/// done once the position is the same on two polls in a row. Fails with
+/// `StillMoving` once `deadline` has passed.
+pub async fn wait_for_idle(
+    device: &Device,
+    poll_interval: std::time::Duration,
+    deadline: std::time::Duration,
+) -> Result<PtzStatus, DeviceError> {
+    let start = std::time::Instant::now();
+    let mut previous: Option<PtzStatus> = None;
+
+    loop {
+        // The camera may still report idle right after acknowledging a move.
+        tokio::time::sleep(poll_interval).await;
+        let status = get_ptz_status(device).await?;
+
+        let reports_move_status = status.pan_tilt_move.is_some() || status.zoom_move.is_some();
+        let arrived = if reports_move_status {
+            status.is_idle()
+        } else {
+            previous
+                .as_ref()
+                .map_or(false, |previous| same_position(previous, &status))
+        };
+        if arrived {
+            return Ok(status);
+        }
+        if start.elapsed() >= deadline {
+            return Err(DeviceError::StillMoving(Box::new(status)));
+        }
+        previous = Some(status);
+    }
+}
+
+/// `wait_for_idle`, returning the final position as the camera reports it,
+/// with its space URIs.
+pub async fn wait_until_idle(
+    device: &Device,
+    poll_interval: std::time::Duration,
+    timeout: std::time::Duration,
+) -> Result<schema::onvif::Ptzvector, DeviceError> {
+    wait_for_idle(device, poll_interval, timeout).await?;
+    get_position(device)
+        .await?
+        .ok_or(DeviceError::Unsupported("position in PTZ status"))
+}
+
+/// How `translate_recenter` moves the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoveStrategy {
+    /// One relative move, the most accurate where it works.
+    Relative,
+    /// A continuous move, stopped after a time worked out from the
+    /// translation.
+    Continuous,
+}
+
+/// Pick the strategy once per device and log why: the device's own
+/// override, then the per-model overrides, then whether the PTZ node
+/// advertises a relative pan/tilt translation space.
+pub async fn move_strategy(device: &Device) -> MoveStrategy {
+    if let Some(strategy) = *device.move_strategy.lock().unwrap() {
+        return strategy;
+    }
+
+    let (strategy, reason) = match device.config.forced_move_strategy() {
+        Some(strategy) => (strategy, "configured for this camera".to_string()),
+        None => detect_move_strategy(device).await,
+    };
+    info!("recentering with {:?} moves: {}", strategy, reason);
+    *device.move_strategy.lock().unwrap() = Some(strategy);
+    strategy
+}
+
+async fn detect_move_strategy(device: &Device) -> (MoveStrategy, String) {
+    match device.info().await {
+        Ok(info) => {
+            if let Some(strategy) = device.config.forced_model_move_strategy(&info.model) {
+                return (strategy, format!("configured for model {}", info.model));
+            }
+        }
+        Err(e) => debug!("no model to check the move strategy overrides: {}", e),
+    }
+    if let Some(strategy) = quirks::quirks(device).await.move_strategy {
+        return (strategy, "forced by the model's quirks".to_string());
+    }
+
+    match get_ptz_node(device).await {
+        Ok(node) if can_emulate_relative(&PtzNodeInfo::from(&node), device) => (
+            MoveStrategy::Relative,
+            "relative moves emulated with absolute moves".to_string(),
+        ),
+        Ok(node)
+            if PtzNodeInfo::from(&node)
+                .spaces
+                .relative_pan_tilt_translation
+                .is_empty() =>
+        {
+            (
+                MoveStrategy::Continuous,
+                "no relative pan/tilt translation space".to_string(),
+            )
+        }
+        Ok(_) if !device.supports_relative_move() => (
+            MoveStrategy::Continuous,
+            "relative moves not supported".to_string(),
+        ),
+        Ok(_) => (
+            MoveStrategy::Relative,
+            "relative pan/tilt translation space advertised".to_string(),
+        ),
+        Err(e) => (
+            MoveStrategy::Continuous,
+            format!("failed to fetch the PTZ node: {}", e),
+        ),
+    }
+}
+
+/// How long after a timed move should have stopped the camera stops it by
+/// itself.
+pub(crate) const RECENTER_STOP_MARGIN: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How long a continuous move runs before `translate_recenter` stops it.
+#[derive(Debug, Clone, Copy)]
+pub struct RecenterConfig {
+    /// Milliseconds of movement per unit of requested translation.
+    pub speed_gain_ms: f64,
+    /// Upper bound on the move duration, regardless of the translation.
+    pub max_duration_ms: u64,
+    /// How much narrower the field of view is at full zoom, minus one: the
+    /// translation is divided by `1 + fov_scale * zoom`. 0 ignores the zoom.
+    pub fov_scale: f64,
+    /// Clicks closer to the centre than this, in normalized units, don't
+    /// move the camera.
+    pub dead_zone: f64,
+}
+
+impl Default for RecenterConfig {
+    fn default() -> Self {
+        Self {
+            speed_gain_ms: 500.0,
+            max_duration_ms: 2000,
+            fov_scale: 9.0,
+            dead_zone: 0.02,
+        }
+    }
+}
+
+impl RecenterConfig {
+    pub fn in_dead_zone(&self, x: i32, y: i32, rect_width: i32, rect_height: i32) -> bool {
+        let pan = x as f64 / rect_width as f64;
+        let tilt = y as f64 / rect_height as f64;
+        (pan * pan + tilt * tilt).sqrt() < self.dead_zone
+    }
+
+    pub fn duration(&self, pan: f64, tilt: f64) -> std::time::Duration {
+        let ms = (self.speed_gain_ms * (pan * pan + tilt * tilt).sqrt()).max(0.0) as u64;
+        std::time::Duration::from_millis(ms.min(self.max_duration_ms))
+    }
+}
+
+/// Normalized pan and tilt for a click at `x`, `y` pixels from the image
+/// centre, `y` positive above it, scaled down by the current zoom since a
+/// narrower field of view means a smaller angle per pixel. Both move
+/// strategies send the result as is, mounts that move the other way are
+/// handled by `Orientation`.
+pub(crate) async fn recenter_translation(
+    device: &Device,
+    config: &RecenterConfig,
+    x: i32,
+    y: i32,
+    rect_width: i32,
+    rect_height: i32,
+) -> (f64, f64) {
+    let zoom = if config.fov_scale == 0.0 {
+        0.0
+    } else {
+        current_zoom(device).await
+    };
+    scaled_translation(config, x, y, rect_width, rect_height, zoom)
+}
+
+/// Zoom level from GetStatus, 0 (wide) if the camera doesn't report one.
+async fn current_zoom(device: &Device) -> f64 {
+    match get_ptz_status(device).await {
+        Ok(status) => status.zoom.unwrap_or(0.0).clamp(0.0, 1.0),
+        Err(e) => {
+            debug!("no zoom level for recentering: {}", e);
+            0.0
+        }
+    }
+}
+
+fn scaled_translation(
+    config: &RecenterConfig,
+    x: i32,
+    y: i32,
+    rect_width: i32,
+    rect_height: i32,
+    zoom: f64,
+) -> (f64, f64) {
+    // Clicks in the corners of a wide rectangle land slightly beyond 1.
+    let pan = (x as f64 / rect_width as f64).clamp(-1.0, 1.0);
+    let tilt = (y as f64 / rect_height as f64).clamp(-1.0, 1.0);
+    let scale = 1.0 + config.fov_scale * zoom;
+    (pan / scale, tilt / scale)
+}
+
+pub async fn translate_recenter(
+    device: &Device,
+    config: &RecenterConfig,
+    x: i32,
+    y: i32,
+    rect_width: i32,
+    rect_height: i32,
+) -> Result<(), DeviceError> {
+    if config.in_dead_zone(x, y, rect_width, rect_height) {
+        info!(
+            "click at ({}, {}) is within the dead zone, not moving",
+            x, y
+        );
+        return Ok(());
+    }
+
+    let (pan, tilt) = recenter_translation(device, config, x, y, rect_width, rect_height).await;
+    let zoom = 0.0;
+
+    if move_strategy(device).await == MoveStrategy::Relative {
+        debug!("recentering with a relative move");
+        return send_relative_ptz(device, pan, tilt, zoom, None, None).await;
+    }
+
+    debug!("recentering with a timed continuous move");
+    continuous_move_for(device, pan, tilt, zoom, config.duration(pan, tilt)).await
+}
+
+/// Boxes smaller than this fraction of the frame on both sides are clicks
+/// and only recenter.
+const MIN_ZOOM_RECT: f64 = 0.05;
+
+/// Centre on a box the operator drew and zoom until it fills the frame.
+/// `cx`, `cy` are the box centre as for `translate_recenter`, relative to
+/// the centre of a `frame_w` by `frame_h` image. The zoom comes from the
+/// same field of view model, so `config.fov_scale` must not be 0.
+#[allow(clippy::too_many_arguments)]
+pub async fn zoom_to_rect(
+    device: &Device,
+    config: &RecenterConfig,
+    cx: i32,
+    cy: i32,
+    rect_w: i32,
+    rect_h: i32,
+    frame_w: i32,
+    frame_h: i32,
+) -> Result<(), DeviceError> {
+    if frame_w <= 0 || frame_h <= 0 {
+        return Err(DeviceError::InvalidArgument(format!(
+            "frame {}x{} is empty",
+            frame_w, frame_h
+        )));
+    }
+    // Fit the box's longer side, relative to the frame, so all of it stays
+    // in view.
+    let ratio = (rect_w.abs() as f64 / frame_w as f64).max(rect_h.abs() as f64 / frame_h as f64);
+    if ratio < MIN_ZOOM_RECT {
+        return translate_recenter(device, config, cx, cy, frame_w, frame_h).await;
+    }
+    if config.fov_scale <= 0.0 {
+        return Err(DeviceError::InvalidArgument(
+            "area zoom needs a field of view scale above 0".to_string(),
+        ));
+    }
+
+    let zoom = current_zoom(device).await;
+    let (pan, tilt) = scaled_translation(config, cx, cy, frame_w, frame_h, zoom);
+    // The field of view narrows by 1 + fov_scale * zoom, the box has to be
+    // magnified by 1 / ratio on top of the current level.
+    let scale = (1.0 + config.fov_scale * zoom) / ratio.min(1.0);
+    let target = ((scale - 1.0) / config.fov_scale).clamp(0.0, 1.0);
+    info!(
+        "area zoom on ({}, {}) {}x{}: zoom {:.3} -> {:.3}",
+        cx, cy, rect_w, rect_h, zoom, target
+    );
+
+    if move_strategy(device).await == MoveStrategy::Relative {
+        debug!("area zoom with a relative move");
+        return send_relative_ptz(device, pan, tilt, target - zoom, None, None).await;
+    }
+
+    debug!("area zoom with a timed continuous move, then an absolute zoom");
+    if !config.in_dead_zone(cx, cy, frame_w, frame_h) {
+        continuous_move_for(device, pan, tilt, 0.0, config.duration(pan, tilt)).await?;
+    }
+    zoom_absolute(device, target).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn velocity_space() -> MoveSpace {
+        MoveSpace::generic((-1.0, 1.0))
+    }
+
+    #[test]
+    fn invert_pan_mirrors_pan_only() {
+        let orientation = Orientation {
+            invert_pan: true,
+            ..Default::default()
+        };
+        let (pan, tilt, zoom) = velocity_space().orient(orientation, 0.5, 0.25, 0.1);
+        assert_eq!((pan, tilt, zoom), (-0.5, 0.25, 0.1));
+    }
+
+    #[test]
+    fn invert_mirrors_around_the_middle_of_the_range() {
+        let orientation = Orientation {
+            invert_zoom: true,
+            ..Default::default()
+        };
+        let (_, _, zoom) = MoveSpace::generic((0.0, 1.0)).orient(orientation, 0.0, 0.0, 0.2);
+        assert!((zoom - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn swap_happens_before_inverting() {
+        let orientation = Orientation {
+            invert_pan: true,
+            swap_pan_tilt: true,
+            ..Default::default()
+        };
+        let (pan, tilt, _) = velocity_space().orient(orientation, 0.5, 0.25, 0.0);
+        assert_eq!((pan, tilt), (-0.25, 0.5));
+    }
+
+    #[test]
+    fn clamp_axis_rejects_nan_even_when_lenient() {
+        assert!(matches!(
+            clamp_axis("pan", f64::NAN, (-1.0, 1.0), false),
+            Err(DeviceError::InvalidArgument(_))
+        ));
+        assert!(clamp_axis("pan", f64::INFINITY, (-1.0, 1.0), false).is_err());
+    }
+
+    #[test]
+    fn clamp_axis_keeps_values_at_the_limit() {
+        assert_eq!(clamp_axis("pan", 1.0, (-1.0, 1.0), true).unwrap(), 1.0);
+        assert_eq!(clamp_axis("pan", -1.0, (-1.0, 1.0), true).unwrap(), -1.0);
+    }
+
+    #[test]
+    fn clamp_axis_beyond_the_limit() {
+        assert_eq!(clamp_axis("pan", 1.5, (-1.0, 1.0), false).unwrap(), 1.0);
+        assert_eq!(clamp_axis("pan", -7.0, (-1.0, 1.0), false).unwrap(), -1.0);
+        assert!(matches!(
+            clamp_axis("pan", 1.5, (-1.0, 1.0), true),
+            Err(DeviceError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn move_space_clamps_each_axis_to_its_own_range() {
+        let space = MoveSpace {
+            pan: (-180.0, 180.0),
+            tilt: (-90.0, 0.0),
+            ..MoveSpace::generic((0.0, 1.0))
+        };
+        assert_eq!(
+            space.clamp(200.0, 10.0, 2.0, false).unwrap(),
+            (180.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            space.clamp(-180.0, -90.0, 0.0, true).unwrap(),
+            (-180.0, -90.0, 0.0)
+        );
+        assert!(space.clamp(0.0, f64::NAN, 0.5, false).is_err());
+        assert!(space.clamp(0.0, 0.0, 1.5, true).is_err());
+    }
+
+    #[test]
+    fn durations_round_trip_below_a_second() {
+        let duration = std::time::Duration::from_millis(250);
+        assert_eq!(xsd_duration(duration).seconds, 0.25);
+        assert_eq!(std_duration(&xsd_duration(duration)), duration);
+    }
+
+    #[test]
+    fn durations_round_trip_over_several_minutes() {
+        let duration = std::time::Duration::from_millis(330_500);
+        assert_eq!(std_duration(&xsd_duration(duration)), duration);
+    }
+
+    #[test]
+    fn std_duration_adds_up_every_field() {
+        let duration = xsd_types::types::duration::Duration {
+            days: 1,
+            hours: 2,
+            minutes: 3,
+            seconds: 4.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            std_duration(&duration),
+            std::time::Duration::from_secs_f64(93784.5)
+        );
+    }
+
+    #[test]
+    fn recenter_translation_points_at_the_click() {
+        let config = RecenterConfig::default();
+        let (pan, tilt) = scaled_translation(&config, 160, 120, 320, 240, 0.0);
+        assert_eq!((pan, tilt), (0.5, 0.5));
+        let (pan, tilt) = scaled_translation(&config, -160, -120, 320, 240, 0.0);
+        assert_eq!((pan, tilt), (-0.5, -0.5));
+    }
+
+    #[test]
+    fn recenter_translation_shrinks_with_zoom() {
+        let config = RecenterConfig {
+            fov_scale: 9.0,
+            ..Default::default()
+        };
+        let (pan, tilt) = scaled_translation(&config, 320, 240, 320, 240, 1.0);
+        assert!((pan - 0.1).abs() < 1e-9);
+        assert!((tilt - 0.1).abs() < 1e-9);
+    }
+}
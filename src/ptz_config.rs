@@ -0,0 +1,388 @@
+use std::time::Duration;
+
+use onvif::schema;
+use tracing::info;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::media::{get_profile_by_token, resolve_profile_token};
+use crate::ptz::{get_ptz_configuration, std_duration, xsd_duration};
+
+/// PTZ configuration settings to change with `set_ptz_configuration`, `None`
+/// keeps the current value.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SetPtzConfigParams {
+    /// DefaultPTZSpeed for pan and tilt.
+    pub pan_tilt_speed: Option<(f64, f64)>,
+    pub zoom_speed: Option<f64>,
+    /// DefaultPTZTimeout, how long continuous moves last without a stop.
+    pub timeout: Option<Duration>,
+    /// Limits as (min, max) in the absolute position space.
+    pub pan_limits: Option<(f64, f64)>,
+    pub tilt_limits: Option<(f64, f64)>,
+    pub zoom_limits: Option<(f64, f64)>,
+}
+
+fn check_range(
+    what: &str,
+    value: f64,
+    range: Option<&schema::onvif::FloatRange>,
+) -> Result<(), DeviceError> {
+    if !value.is_finite() {
+        return Err(DeviceError::InvalidArgument(format!(
+            "{} is {}",
+            what, value
+        )));
+    }
+    match range {
+        Some(range) if value < range.min || value > range.max => {
+            Err(DeviceError::InvalidArgument(format!(
+                "{} {} is outside [{}, {}]",
+                what, value, range.min, range.max
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_limits(
+    what: &str,
+    (min, max): (f64, f64),
+    range: Option<&schema::onvif::FloatRange>,
+) -> Result<(), DeviceError> {
+    if min > max {
+        return Err(DeviceError::InvalidArgument(format!(
+            "{} limits {} > {}",
+            what, min, max
+        )));
+    }
+    check_range(what, min, range)?;
+    check_range(what, max, range)
+}
+
+/// Check `params` against the ranges the camera offers. Ranges it leaves out
+/// aren't checked.
+fn validate(
+    params: &SetPtzConfigParams,
+    options: &schema::onvif::PtzconfigurationOptions,
+) -> Result<(), DeviceError> {
+    let spaces = &options.spaces;
+    let pan_tilt_speed = spaces.pan_tilt_speed_space.first().map(|s| &s.x_range);
+    let zoom_speed = spaces.zoom_speed_space.first().map(|s| &s.x_range);
+    let pan_tilt_position = spaces.absolute_pan_tilt_position_space.first();
+    let zoom_position = spaces.absolute_zoom_position_space.first();
+
+    if let Some((pan, tilt)) = params.pan_tilt_speed {
+        check_range("pan speed", pan, pan_tilt_speed)?;
+        check_range("tilt speed", tilt, pan_tilt_speed)?;
+    }
+    if let Some(zoom) = params.zoom_speed {
+        check_range("zoom speed", zoom, zoom_speed)?;
+    }
+    if let Some(timeout) = params.timeout {
+        let min = std_duration(&options.ptz_timeout.min);
+        let max = std_duration(&options.ptz_timeout.max);
+        if timeout < min || timeout > max {
+            return Err(DeviceError::InvalidArgument(format!(
+                "timeout {:?} is outside [{:?}, {:?}]",
+                timeout, min, max
+            )));
+        }
+    }
+    if let Some(limits) = params.pan_limits {
+        check_limits("pan", limits, pan_tilt_position.map(|s| &s.x_range))?;
+    }
+    if let Some(limits) = params.tilt_limits {
+        check_limits("tilt", limits, pan_tilt_position.map(|s| &s.y_range))?;
+    }
+    if let Some(limits) = params.zoom_limits {
+        check_limits("zoom", limits, zoom_position.map(|s| &s.x_range))?;
+    }
+    Ok(())
+}
+
+/// Replace the given fields of `config`. Limits a configuration doesn't have
+/// yet start out as the full absolute space, so setting only pan leaves tilt
+/// unrestricted.
+fn apply(
+    config: &mut schema::onvif::Ptzconfiguration,
+    params: &SetPtzConfigParams,
+    options: &schema::onvif::PtzconfigurationOptions,
+) {
+    if params.pan_tilt_speed.is_some() || params.zoom_speed.is_some() {
+        let speed = config
+            .default_ptz_speed
+            .get_or_insert_with(Default::default);
+        if let Some((pan, tilt)) = params.pan_tilt_speed {
+            let pan_tilt = speed.pan_tilt.get_or_insert_with(Default::default);
+            pan_tilt.x = pan;
+            pan_tilt.y = tilt;
+        }
+        if let Some(zoom) = params.zoom_speed {
+            speed.zoom.get_or_insert_with(Default::default).x = zoom;
+        }
+    }
+
+    if let Some(timeout) = params.timeout {
+        config.default_ptz_timeout = Some(xsd_duration(timeout));
+    }
+
+    if params.pan_limits.is_some() || params.tilt_limits.is_some() {
+        let limits = config
+            .pan_tilt_limits
+            .get_or_insert_with(|| schema::onvif::PanTiltLimits {
+                range: options
+                    .spaces
+                    .absolute_pan_tilt_position_space
+                    .first()
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        if let Some((min, max)) = params.pan_limits {
+            limits.range.x_range = schema::onvif::FloatRange { min, max };
+        }
+        if let Some((min, max)) = params.tilt_limits {
+            limits.range.y_range = schema::onvif::FloatRange { min, max };
+        }
+    }
+
+    if let Some((min, max)) = params.zoom_limits {
+        let limits = config
+            .zoom_limits
+            .get_or_insert_with(|| schema::onvif::ZoomLimits {
+                range: options
+                    .spaces
+                    .absolute_zoom_position_space
+                    .first()
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        limits.range.x_range = schema::onvif::FloatRange { min, max };
+    }
+}
+
+/// Change the PTZ configuration of `profile` or the first profile. The
+/// current configuration is fetched, the given fields replaced and the
+/// result saved across reboots, so settings this doesn't know about are
+/// sent back unchanged. Values outside the configuration options are
+/// rejected before sending.
+pub async fn set_ptz_configuration(
+    device: &Device,
+    profile: Option<schema::onvif::ReferenceToken>,
+    params: SetPtzConfigParams,
+) -> Result<(), DeviceError> {
+    let ptz = &*device.ptz_client()?;
+    let profile_token = resolve_profile_token(device, profile).await?;
+    let configuration_token = get_profile_by_token(device, &profile_token)
+        .await?
+        .ptz_configuration
+        .map(|config| config.token)
+        .ok_or(DeviceError::Unsupported("PTZ on this profile"))?;
+
+    let mut configuration = get_ptz_configuration(device, configuration_token.clone()).await?;
+    let options = schema::ptz::get_configuration_options(
+        ptz,
+        &schema::ptz::GetConfigurationOptions {
+            configuration_token,
+        },
+    )
+    .await?
+    .ptz_configuration_options;
+
+    validate(&params, &options)?;
+    apply(&mut configuration, &params, &options);
+
+    info!(
+        "set PTZ configuration {}: {:?}",
+        configuration.token.0, params
+    );
+    let response = schema::ptz::set_configuration(
+        ptz,
+        &schema::ptz::SetConfiguration {
+            ptz_configuration: configuration,
+            force_persistence: true,
+        },
+    )
+    .await;
+    device.track(response.map_err(DeviceError::from))?;
+
+    // The default speed and limits are cached with the move spaces.
+    device.move_spaces.lock().unwrap().clear();
+    device.emulate_relative.lock().unwrap().clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GetConfiguration and GetConfigurationOptions bodies recorded from a
+    // dome, namespaces trimmed to the ones used.
+    const CONFIGURATION: &str = r#"<tptz:GetConfigurationResponse xmlns:tptz="http://www.onvif.org/ver20/ptz/wsdl" xmlns:tt="http://www.onvif.org/ver10/schema">
+  <tptz:PTZConfiguration token="PTZToken">
+    <tt:Name>PTZ</tt:Name>
+    <tt:UseCount>2</tt:UseCount>
+    <tt:NodeToken>PTZNODETOKEN</tt:NodeToken>
+    <tt:DefaultAbsolutePantTiltPositionSpace>http://www.onvif.org/ver10/tptz/PanTiltSpaces/PositionGenericSpace</tt:DefaultAbsolutePantTiltPositionSpace>
+    <tt:DefaultContinuousPanTiltVelocitySpace>http://www.onvif.org/ver10/tptz/PanTiltSpaces/VelocityGenericSpace</tt:DefaultContinuousPanTiltVelocitySpace>
+    <tt:DefaultPTZSpeed>
+      <tt:PanTilt x="0.5" y="0.5" space="http://www.onvif.org/ver10/tptz/PanTiltSpaces/GenericSpeedSpace"/>
+      <tt:Zoom x="0.25" space="http://www.onvif.org/ver10/tptz/ZoomSpaces/ZoomGenericSpeedSpace"/>
+    </tt:DefaultPTZSpeed>
+    <tt:DefaultPTZTimeout>PT5S</tt:DefaultPTZTimeout>
+  </tptz:PTZConfiguration>
+</tptz:GetConfigurationResponse>"#;
+
+    const OPTIONS: &str = r#"<tptz:GetConfigurationOptionsResponse xmlns:tptz="http://www.onvif.org/ver20/ptz/wsdl" xmlns:tt="http://www.onvif.org/ver10/schema">
+  <tptz:PTZConfigurationOptions>
+    <tt:Spaces>
+      <tt:AbsolutePanTiltPositionSpace>
+        <tt:URI>http://www.onvif.org/ver10/tptz/PanTiltSpaces/PositionGenericSpace</tt:URI>
+        <tt:XRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:XRange>
+        <tt:YRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:YRange>
+      </tt:AbsolutePanTiltPositionSpace>
+      <tt:AbsoluteZoomPositionSpace>
+        <tt:URI>http://www.onvif.org/ver10/tptz/ZoomSpaces/PositionGenericSpace</tt:URI>
+        <tt:XRange><tt:Min>0</tt:Min><tt:Max>1</tt:Max></tt:XRange>
+      </tt:AbsoluteZoomPositionSpace>
+      <tt:PanTiltSpeedSpace>
+        <tt:URI>http://www.onvif.org/ver10/tptz/PanTiltSpaces/GenericSpeedSpace</tt:URI>
+        <tt:XRange><tt:Min>0</tt:Min><tt:Max>1</tt:Max></tt:XRange>
+      </tt:PanTiltSpeedSpace>
+      <tt:ZoomSpeedSpace>
+        <tt:URI>http://www.onvif.org/ver10/tptz/ZoomSpaces/ZoomGenericSpeedSpace</tt:URI>
+        <tt:XRange><tt:Min>0</tt:Min><tt:Max>1</tt:Max></tt:XRange>
+      </tt:ZoomSpeedSpace>
+    </tt:Spaces>
+    <tt:PTZTimeout><tt:Min>PT1S</tt:Min><tt:Max>PT1M</tt:Max></tt:PTZTimeout>
+  </tptz:PTZConfigurationOptions>
+</tptz:GetConfigurationOptionsResponse>"#;
+
+    fn recorded() -> (
+        schema::onvif::Ptzconfiguration,
+        schema::onvif::PtzconfigurationOptions,
+    ) {
+        let configuration: schema::ptz::GetConfigurationResponse =
+            yaserde::de::from_str(CONFIGURATION).unwrap();
+        let options: schema::ptz::GetConfigurationOptionsResponse =
+            yaserde::de::from_str(OPTIONS).unwrap();
+        (
+            configuration.ptz_configuration,
+            options.ptz_configuration_options,
+        )
+    }
+
+    #[test]
+    fn apply_keeps_the_fields_it_doesnt_set() {
+        let (mut configuration, options) = recorded();
+        let params = SetPtzConfigParams {
+            pan_tilt_speed: Some((0.8, 0.6)),
+            pan_limits: Some((-0.5, 0.5)),
+            ..Default::default()
+        };
+        validate(&params, &options).unwrap();
+        apply(&mut configuration, &params, &options);
+
+        assert_eq!(configuration.token.0, "PTZToken");
+        assert_eq!(configuration.node_token.0, "PTZNODETOKEN");
+        let speed = configuration.default_ptz_speed.as_ref().unwrap();
+        let pan_tilt = speed.pan_tilt.as_ref().unwrap();
+        assert_eq!((pan_tilt.x, pan_tilt.y), (0.8, 0.6));
+        // The recorded speed space and zoom speed are sent back as they were.
+        assert_eq!(
+            pan_tilt.space.as_deref(),
+            Some("http://www.onvif.org/ver10/tptz/PanTiltSpaces/GenericSpeedSpace")
+        );
+        assert_eq!(speed.zoom.as_ref().unwrap().x, 0.25);
+        assert_eq!(
+            std_duration(configuration.default_ptz_timeout.as_ref().unwrap()),
+            Duration::from_secs(5)
+        );
+
+        // Tilt starts out as the full absolute range.
+        let limits = &configuration.pan_tilt_limits.as_ref().unwrap().range;
+        assert_eq!((limits.x_range.min, limits.x_range.max), (-0.5, 0.5));
+        assert_eq!((limits.y_range.min, limits.y_range.max), (-1.0, 1.0));
+        assert!(configuration.zoom_limits.is_none());
+
+        let written = yaserde::ser::to_string(&configuration).unwrap();
+        assert!(written.contains("PositionGenericSpace"));
+        assert!(written.contains("VelocityGenericSpace"));
+        assert!(written.contains("PTZNODETOKEN"));
+    }
+
+    #[test]
+    fn apply_sets_timeout_and_zoom_limits() {
+        let (mut configuration, options) = recorded();
+        let params = SetPtzConfigParams {
+            timeout: Some(Duration::from_secs(30)),
+            zoom_limits: Some((0.0, 0.5)),
+            ..Default::default()
+        };
+        validate(&params, &options).unwrap();
+        apply(&mut configuration, &params, &options);
+
+        assert_eq!(
+            std_duration(configuration.default_ptz_timeout.as_ref().unwrap()),
+            Duration::from_secs(30)
+        );
+        let zoom = &configuration.zoom_limits.as_ref().unwrap().range.x_range;
+        assert_eq!((zoom.min, zoom.max), (0.0, 0.5));
+        assert!(configuration.pan_tilt_limits.is_none());
+    }
+
+    #[test]
+    fn validate_rejects_values_outside_the_options() {
+        let (_, options) = recorded();
+        let rejected = [
+            SetPtzConfigParams {
+                pan_tilt_speed: Some((1.5, 0.5)),
+                ..Default::default()
+            },
+            SetPtzConfigParams {
+                zoom_speed: Some(f64::NAN),
+                ..Default::default()
+            },
+            SetPtzConfigParams {
+                timeout: Some(Duration::from_secs(120)),
+                ..Default::default()
+            },
+            SetPtzConfigParams {
+                timeout: Some(Duration::from_millis(500)),
+                ..Default::default()
+            },
+            SetPtzConfigParams {
+                tilt_limits: Some((0.5, -0.5)),
+                ..Default::default()
+            },
+            SetPtzConfigParams {
+                zoom_limits: Some((0.0, 2.0)),
+                ..Default::default()
+            },
+        ];
+        for params in rejected {
+            assert!(
+                matches!(
+                    validate(&params, &options),
+                    Err(DeviceError::InvalidArgument(_))
+                ),
+                "{:?} was accepted",
+                params
+            );
+        }
+    }
+
+    #[test]
+    fn validate_accepts_the_limits_themselves() {
+        let (_, options) = recorded();
+        let params = SetPtzConfigParams {
+            pan_tilt_speed: Some((0.0, 1.0)),
+            zoom_speed: Some(1.0),
+            timeout: Some(Duration::from_secs(60)),
+            pan_limits: Some((-1.0, 1.0)),
+            tilt_limits: Some((-1.0, 1.0)),
+            zoom_limits: Some((0.0, 1.0)),
+        };
+        validate(&params, &options).unwrap();
+    }
+}
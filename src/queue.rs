@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::device::Device;
+
+/// Runs the PTZ commands of one device one at a time, in the order they
+/// were submitted. Cameras fault or queue overlapping commands on their own
+/// otherwise.
+#[derive(Debug, Default)]
+pub(crate) struct CommandQueue {
+    /// Tokio's mutex hands out the lock in the order it was asked for.
+    lock: Mutex<()>,
+    depth: AtomicUsize,
+    /// Ticket of the newest continuous move, older ones are dropped.
+    latest_continuous: AtomicU64,
+    /// Stops sent so far, so multi-step moves can tell they were halted.
+    stops: AtomicU64,
+}
+
+/// Held while a command runs, the next one starts when it is dropped.
+pub(crate) struct Turn<'a> {
+    _lock: MutexGuard<'a, ()>,
+    _depth: Depth<'a>,
+}
+
+/// Counts a command from submission until it is done, or dropped while
+/// still waiting.
+struct Depth<'a>(&'a AtomicUsize);
+
+impl<'a> Depth<'a> {
+    fn new(depth: &'a AtomicUsize) -> Self {
+        depth.fetch_add(1, Ordering::Relaxed);
+        Self(depth)
+    }
+}
+
+impl Drop for Depth<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl CommandQueue {
+    pub(crate) async fn turn(&self) -> Turn<'_> {
+        let depth = Depth::new(&self.depth);
+        Turn {
+            _lock: self.lock.lock().await,
+            _depth: depth,
+        }
+    }
+
+    /// Like `turn`, but `None` if a later continuous move or a stop came in
+    /// while this one was waiting, since the camera would only ever see it
+    /// for a moment.
+    pub(crate) async fn continuous_turn(&self) -> Option<Turn<'_>> {
+        let ticket = self.latest_continuous.fetch_add(1, Ordering::SeqCst) + 1;
+        let turn = self.turn().await;
+        if self.latest_continuous.load(Ordering::SeqCst) == ticket {
+            Some(turn)
+        } else {
+            None
+        }
+    }
+
+    /// Stops don't wait for their turn. This drops the continuous moves
+    /// still waiting so they don't start the camera again after it.
+    pub(crate) fn jump(&self) {
+        self.latest_continuous.fetch_add(1, Ordering::SeqCst);
+        self.stops.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn stops(&self) -> u64 {
+        self.stops.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+impl Device {
+    /// PTZ commands running or waiting for their turn.
+    pub fn ptz_queue_depth(&self) -> usize {
+        self.ptz_queue.depth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn interleaved_commands_run_one_at_a_time_in_order() {
+        let queue = Arc::new(CommandQueue::default());
+        let ran = Arc::new(StdMutex::new(vec![]));
+        let running = Arc::new(AtomicUsize::new(0));
+
+        // Hold the queue until every command is waiting behind it.
+        let first = queue.turn().await;
+        let mut tasks = vec![];
+        for i in 0..20 {
+            let (queue, ran, running) = (queue.clone(), ran.clone(), running.clone());
+            tasks.push(tokio::spawn(async move {
+                let turn = if i % 2 == 0 {
+                    Some(queue.turn().await)
+                } else {
+                    queue.continuous_turn().await
+                };
+                if turn.is_none() {
+                    return;
+                }
+                assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                ran.lock().unwrap().push(i);
+                running.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+        for _ in 0..5 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.depth(), 21);
+        drop(first);
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // Every move ran in submission order, except the continuous ones a
+        // later continuous move replaced while they waited.
+        let mut expected: Vec<_> = (0..20).step_by(2).collect();
+        expected.push(19);
+        assert_eq!(*ran.lock().unwrap(), expected);
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_stop_drops_the_waiting_continuous_move() {
+        let queue = Arc::new(CommandQueue::default());
+        let first = queue.turn().await;
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.continuous_turn().await.is_some() }
+        });
+        tokio::task::yield_now().await;
+        queue.jump();
+        drop(first);
+        assert!(!waiting.await.unwrap());
+        assert_eq!(queue.stops(), 1);
+    }
+}
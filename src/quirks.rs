@@ -0,0 +1,181 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::ptz::{MoveStrategy, Orientation};
+
+/// How a continuous move's timeout is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeoutFormat {
+    /// e.g. `PT1.5S`.
+    Seconds,
+    /// Rounded up to whole seconds, for cameras that reject fractions.
+    WholeSeconds,
+    /// Left out, the camera uses its configured default.
+    Omit,
+}
+
+impl Default for TimeoutFormat {
+    fn default() -> Self {
+        Self::Seconds
+    }
+}
+
+/// Model-specific oddities the move helpers work around.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// Added to the device's configured orientation.
+    pub invert_pan: bool,
+    pub invert_tilt: bool,
+    pub invert_zoom: bool,
+    /// Continuous velocities are multiplied by these, 1 if unset.
+    pub pan_velocity_scale: Option<f64>,
+    pub tilt_velocity_scale: Option<f64>,
+    pub zoom_velocity_scale: Option<f64>,
+    pub move_strategy: Option<MoveStrategy>,
+    /// Absolute moves without a speed are ignored or rejected.
+    pub absolute_needs_speed: bool,
+    /// Relative moves are advertised but broken, do them as absolute moves
+    /// from the current position.
+    pub emulate_relative: bool,
+    /// Pan turns endlessly, so positions past one end of the absolute range
+    /// continue from the other.
+    pub pan_wraps: bool,
+    pub timeout_format: TimeoutFormat,
+}
+
+impl Quirks {
+    /// `orientation` with the inversions of these quirks flipped on top.
+    pub fn orient(&self, orientation: Orientation) -> Orientation {
+        Orientation {
+            invert_pan: orientation.invert_pan != self.invert_pan,
+            invert_tilt: orientation.invert_tilt != self.invert_tilt,
+            invert_zoom: orientation.invert_zoom != self.invert_zoom,
+            swap_pan_tilt: orientation.swap_pan_tilt,
+        }
+    }
+
+    pub fn scale_velocity(&self, pan: f64, tilt: f64, zoom: f64) -> (f64, f64, f64) {
+        (
+            pan * self.pan_velocity_scale.unwrap_or(1.0),
+            tilt * self.tilt_velocity_scale.unwrap_or(1.0),
+            zoom * self.zoom_velocity_scale.unwrap_or(1.0),
+        )
+    }
+
+    pub(crate) fn timeout(
+        &self,
+        timeout: Option<std::time::Duration>,
+    ) -> Option<xsd_types::types::duration::Duration> {
+        let timeout = match self.timeout_format {
+            TimeoutFormat::Seconds => timeout,
+            TimeoutFormat::WholeSeconds => timeout
+                .map(|timeout| std::time::Duration::from_secs(timeout.as_secs_f64().ceil() as u64)),
+            TimeoutFormat::Omit => None,
+        };
+        timeout.map(crate::ptz::xsd_duration)
+    }
+}
+
+/// Quirks of the cameras whose manufacturer and model contain the given
+/// strings, ignoring case. Empty strings match anything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuirksEntry {
+    #[serde(default)]
+    pub manufacturer: String,
+    #[serde(default)]
+    pub model: String,
+    /// Why the entry exists, for whoever edits the file next.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub quirks: Quirks,
+}
+
+impl QuirksEntry {
+    fn matches(&self, manufacturer: &str, model: &str) -> bool {
+        let contains =
+            |value: &str, part: &str| value.to_lowercase().contains(&part.to_lowercase());
+        contains(manufacturer, &self.manufacturer) && contains(model, &self.model)
+    }
+}
+
+/// Quirk entries, searched in order. `load` puts the entries of a file in
+/// front of the built-in ones so they take precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuirksRegistry {
+    entries: Vec<QuirksEntry>,
+}
+
+impl Default for QuirksRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl QuirksRegistry {
+    /// The entries in `quirks.json`, compiled in.
+    pub fn builtin() -> Self {
+        Self {
+            entries: serde_json::from_str(include_str!("quirks.json"))
+                .expect("built-in quirks.json is valid"),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DeviceError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| DeviceError::Config(format!("{}: {}", path.display(), e)))?;
+        let mut entries: Vec<QuirksEntry> = serde_json::from_str(&contents)
+            .map_err(|e| DeviceError::Config(format!("{}: {}", path.display(), e)))?;
+        entries.extend(Self::builtin().entries);
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[QuirksEntry] {
+        &self.entries
+    }
+
+    pub fn lookup(&self, manufacturer: &str, model: &str) -> Option<&QuirksEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.matches(manufacturer, model))
+    }
+}
+
+/// The quirks of `device`, looked up once from its device information. If
+/// that can't be fetched the device gets no quirks until it reconnects,
+/// rather than asking again before every move.
+pub async fn quirks(device: &Device) -> Quirks {
+    if let Some(ref quirks) = *device.quirks.lock().unwrap() {
+        return quirks.clone();
+    }
+
+    let info = match device.info().await {
+        Ok(info) => info,
+        Err(e) => {
+            debug!("no device information to look up quirks: {}", e);
+            *device.quirks.lock().unwrap() = Some(Quirks::default());
+            return Quirks::default();
+        }
+    };
+    let quirks = match device
+        .config
+        .quirks_registry()
+        .lookup(&info.manufacturer, &info.model)
+    {
+        Some(entry) => {
+            info!(
+                "{} {} has quirks: {:?}",
+                info.manufacturer, info.model, entry.quirks
+            );
+            entry.quirks.clone()
+        }
+        None => Quirks::default(),
+    };
+    *device.quirks.lock().unwrap() = Some(quirks.clone());
+    quirks
+}
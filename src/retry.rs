@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tracing::warn;
+
+use crate::error::DeviceError;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub backoff: f64,
+    pub max_delay: Duration,
+    /// Fraction of each delay that is randomised, between 0.0 and 1.0.
+    pub jitter: f64,
+    /// Also retry operations that are not safe to repeat, like relative moves.
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            backoff: 2.0,
+            max_delay: Duration::from_secs(2),
+            jitter: 0.2,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub async fn run<T, F, Fut>(
+        &self,
+        operation: &str,
+        idempotent: bool,
+        mut f: F,
+    ) -> Result<T, DeviceError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DeviceError>>,
+    {
+        let max_attempts = if idempotent || self.retry_non_idempotent {
+            self.max_attempts.max(1)
+        } else {
+            1
+        };
+
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) if e.is_transient() && attempt < max_attempts => {
+                    let wait = self.jittered(delay);
+                    warn!(
+                        "{} failed (attempt {}/{}): {}, retrying in {:?}",
+                        operation, attempt, max_attempts, e, wait
+                    );
+                    tokio::time::sleep(wait).await;
+                    delay = delay.mul_f64(self.backoff).min(self.max_delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+        delay.mul_f64(factor)
+    }
+}
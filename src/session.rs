@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::ptz::{self, RecenterConfig};
+use crate::tours::{self, Tour};
+
+/// How a motion sequence of a `PtzSession` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionOutcome {
+    Completed,
+    /// A newer command took over before the sequence finished.
+    Superseded,
+}
+
+#[derive(Default)]
+pub(crate) struct Motion {
+    generation: u64,
+    in_flight: Option<CancellationToken>,
+    /// Held by the running sequence, so the next one starts only once the
+    /// previous one has sent its stop.
+    running: Arc<tokio::sync::Mutex<()>>,
+}
+
+type Turn = tokio::sync::OwnedMutexGuard<()>;
+
+/// Serialises motion sequences on one device: starting a new one cancels
+/// the pending steps of the previous one.
+pub struct PtzSession<'a> {
+    device: &'a Device,
+}
+
+impl Device {
+    pub fn ptz_session(&self) -> PtzSession<'_> {
+        PtzSession { device: self }
+    }
+}
+
+impl PtzSession<'_> {
+    /// Cancel whatever is in flight, register a new sequence and wait for the
+    /// previous one to wind down. Returns its turn, generation and token and
+    /// whether an earlier sequence was cut short.
+    async fn begin(&self) -> (Turn, u64, CancellationToken, bool) {
+        let (generation, token, superseded, running) = {
+            let mut motion = self.device.motion.lock().unwrap();
+            let superseded = match motion.in_flight.take() {
+                Some(previous) => {
+                    previous.cancel();
+                    true
+                }
+                None => false,
+            };
+            motion.generation += 1;
+            let token = CancellationToken::new();
+            motion.in_flight = Some(token.clone());
+            (motion.generation, token, superseded, motion.running.clone())
+        };
+        (running.lock_owned().await, generation, token, superseded)
+    }
+
+    fn finish(&self, generation: u64) {
+        let mut motion = self.device.motion.lock().unwrap();
+        if motion.generation == generation {
+            motion.in_flight = None;
+        }
+    }
+
+    /// Like `ptz::translate_recenter`, but a later call aborts the pending
+    /// sleep and stop of this one.
+    pub async fn recenter(
+        &self,
+        config: &RecenterConfig,
+        x: i32,
+        y: i32,
+        rect_width: i32,
+        rect_height: i32,
+    ) -> Result<MotionOutcome, DeviceError> {
+        // Leaves any sequence in flight alone.
+        if config.in_dead_zone(x, y, rect_width, rect_height) {
+            info!(
+                "click at ({}, {}) is within the dead zone, not moving",
+                x, y
+            );
+            return Ok(MotionOutcome::Completed);
+        }
+
+        let (_turn, generation, token, superseded) = self.begin().await;
+        let result = self
+            .run_recenter(&token, superseded, config, x, y, rect_width, rect_height)
+            .await;
+        self.finish(generation);
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_recenter(
+        &self,
+        token: &CancellationToken,
+        superseded: bool,
+        config: &RecenterConfig,
+        x: i32,
+        y: i32,
+        rect_width: i32,
+        rect_height: i32,
+    ) -> Result<MotionOutcome, DeviceError> {
+        let device = self.device;
+        if superseded {
+            ptz::send_stop_ptz(device, ptz::StopAxes::All, None).await?;
+        }
+
+        let (pan, tilt) =
+            ptz::recenter_translation(device, config, x, y, rect_width, rect_height).await;
+        let zoom = 0.0;
+
+        if ptz::move_strategy(device).await == ptz::MoveStrategy::Relative {
+            ptz::send_relative_ptz(device, pan, tilt, zoom, None, None).await?;
+            return Ok(MotionOutcome::Completed);
+        }
+
+        let duration = config.duration(pan, tilt);
+        match ptz::continuous_move_until(device, pan, tilt, zoom, duration, token).await? {
+            true => Ok(MotionOutcome::Completed),
+            false => Ok(MotionOutcome::Superseded),
+        }
+    }
+
+    /// `tours::run_tour` of `tour` in a loop, until a later command
+    /// supersedes it.
+    pub async fn software_tour(&self, tour: &Tour) -> Result<MotionOutcome, DeviceError> {
+        if tour.spots.is_empty() {
+            return Ok(MotionOutcome::Completed);
+        }
+
+        let (_turn, generation, token, _) = self.begin().await;
+        let result = tours::run_tour(self.device, &tour.spots, true, false, &token).await;
+        self.finish(generation);
+        result
+    }
+
+    /// Abort any pending sequence and stop the camera.
+    pub async fn stop(&self) -> Result<(), DeviceError> {
+        let (_turn, generation, _, _) = self.begin().await;
+        let result = ptz::send_stop_ptz(self.device, ptz::StopAxes::All, None).await;
+        self.finish(generation);
+        result
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use onvif::schema;
+use tracing::info;
+
+use crate::device::Device;
+use crate::error::DeviceError;
+
+/// Reboot the camera and return the message it answers with, e.g. how long
+/// the restart takes.
+pub async fn reboot(device: &Device) -> Result<String, DeviceError> {
+    info!("rebooting");
+    let response =
+        schema::devicemgmt::system_reboot(&*device.device_mgmt(), &Default::default()).await?;
+    Ok(response.message)
+}
+
+/// The camera clock as reported by GetSystemDateAndTime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemDateTime {
+    /// The camera takes its time from NTP rather than a manual setting.
+    pub ntp: bool,
+    pub daylight_savings: bool,
+    /// POSIX TZ string, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+    pub time_zone: Option<String>,
+    /// `None` if the camera left it out or sent an invalid date.
+    pub utc: Option<DateTime<Utc>>,
+}
+
+pub async fn get_system_datetime(device: &Device) -> Result<SystemDateTime, DeviceError> {
+    let request = &Default::default();
+    let response = device
+        .retry_policy
+        .run("get_system_date_and_time", true, || async move {
+            schema::devicemgmt::get_system_date_and_time(&*device.device_mgmt(), request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await?
+        .system_date_and_time;
+
+    let utc = response.utc_date_time.and_then(|utc| {
+        let date =
+            NaiveDate::from_ymd_opt(utc.date.year, utc.date.month as u32, utc.date.day as u32)?;
+        let time = date.and_hms_opt(
+            utc.time.hour as u32,
+            utc.time.minute as u32,
+            utc.time.second as u32,
+        )?;
+        Some(DateTime::<Utc>::from_utc(time, Utc))
+    });
+
+    Ok(SystemDateTime {
+        ntp: matches!(response.date_time_type, schema::onvif::SetDateTimeType::Ntp),
+        daylight_savings: response.daylight_savings,
+        time_zone: response.time_zone.map(|tz| tz.tz),
+        utc,
+    })
+}
+
+/// Set the camera clock to `utc`, and its time zone if given. Fails with
+/// `DeviceError::NtpEnabled` if the camera takes its time from NTP.
+pub async fn set_system_datetime(
+    device: &Device,
+    utc: DateTime<Utc>,
+    tz: Option<String>,
+) -> Result<(), DeviceError> {
+    let current = get_system_datetime(device).await?;
+    if current.ntp {
+        return Err(DeviceError::NtpEnabled);
+    }
+
+    info!("set system time: {} ({:?})", utc, tz);
+    let utc = utc.naive_utc();
+    schema::devicemgmt::set_system_date_and_time(
+        &*device.device_mgmt(),
+        &schema::devicemgmt::SetSystemDateAndTime {
+            date_time_type: schema::onvif::SetDateTimeType::Manual,
+            daylight_savings: current.daylight_savings,
+            time_zone: tz
+                .or(current.time_zone)
+                .map(|tz| schema::onvif::TimeZone { tz }),
+            utc_date_time: Some(schema::onvif::DateTime {
+                date: schema::onvif::Date {
+                    year: utc.year(),
+                    month: utc.month() as i32,
+                    day: utc.day() as i32,
+                },
+                time: schema::onvif::Time {
+                    hour: utc.hour() as i32,
+                    minute: utc.minute() as i32,
+                    second: utc.second() as i32,
+                },
+            }),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
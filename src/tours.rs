@@ -0,0 +1,364 @@
+use std::time::Duration;
+
+use onvif::schema;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::device::{Device, Service};
+use crate::error::DeviceError;
+use crate::media::get_profile_token;
+use crate::presets::goto_preset;
+use crate::ptz::{self, get_ptz_node, std_duration, xsd_duration, PtzNodeInfo};
+use crate::session::MotionOutcome;
+
+/// One stop of a tour: move to the preset, then stay for `dwell`.
+#[derive(Debug, Clone)]
+pub struct TourSpot {
+    pub preset_token: String,
+    pub dwell: Duration,
+    pub speed: Option<(f64, f64, f64)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tour {
+    pub name: Option<String>,
+    pub spots: Vec<TourSpot>,
+}
+
+impl Tour {
+    pub fn new(name: Option<String>) -> Self {
+        Self {
+            name,
+            spots: Vec::new(),
+        }
+    }
+
+    pub fn add_tour_spot(
+        mut self,
+        preset_token: String,
+        dwell: Duration,
+        speed: Option<(f64, f64, f64)>,
+    ) -> Self {
+        self.spots.push(TourSpot {
+            preset_token,
+            dwell,
+            speed,
+        });
+        self
+    }
+}
+
+pub(crate) fn ptz_speed((pan, tilt, zoom): (f64, f64, f64)) -> schema::onvif::Ptzspeed {
+    schema::onvif::Ptzspeed {
+        pan_tilt: Some(schema::common::Vector2D {
+            x: pan,
+            y: tilt,
+            space: None,
+        }),
+        zoom: Some(schema::common::Vector1D {
+            x: zoom,
+            space: None,
+        }),
+    }
+}
+
+/// Whether the profile's PTZ node runs preset tours itself. Without them
+/// `PtzSession::software_tour` can stand in.
+pub async fn supports_preset_tours(device: &Device) -> Result<bool, DeviceError> {
+    let node = PtzNodeInfo::from(&get_ptz_node(device).await?);
+    Ok(node.maximum_number_of_preset_tours > 0)
+}
+
+async fn ptz_with_tours(device: &Device) -> Result<Service, DeviceError> {
+    let ptz = device.ptz_client()?;
+    if !supports_preset_tours(device).await? {
+        return Err(DeviceError::Unsupported("preset tours"));
+    }
+    Ok(ptz)
+}
+
+fn preset_tour(token: Option<String>, tour: &Tour) -> schema::onvif::PresetTour {
+    schema::onvif::PresetTour {
+        token: token.map(schema::onvif::ReferenceToken),
+        name: tour.name.clone().map(schema::onvif::Name),
+        tour_spot: tour
+            .spots
+            .iter()
+            .map(|spot| schema::onvif::PtzpresetTourSpot {
+                preset_detail: schema::onvif::PtzpresetTourPresetDetail {
+                    preset_token: Some(schema::onvif::ReferenceToken(spot.preset_token.clone())),
+                    ..Default::default()
+                },
+                speed: spot.speed.map(ptz_speed),
+                stay_time: Some(xsd_duration(spot.dwell)),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Create a native preset tour on the camera and return its token.
+pub async fn create_tour(device: &Device, tour: &Tour) -> Result<String, DeviceError> {
+    let ptz = &*ptz_with_tours(device).await?;
+
+    info!("create preset tour: {:?}", tour.name);
+    let token = schema::ptz::create_preset_tour(
+        ptz,
+        &schema::ptz::CreatePresetTour {
+            profile_token: get_profile_token(device).await?,
+        },
+    )
+    .await?
+    .preset_tour_token
+    .0;
+    modify_tour(device, &token, tour).await?;
+
+    Ok(token)
+}
+
+/// Replace the spots and name of an existing tour.
+pub async fn modify_tour(device: &Device, token: &str, tour: &Tour) -> Result<(), DeviceError> {
+    let ptz = &*ptz_with_tours(device).await?;
+
+    info!("modify preset tour: {}", token);
+    schema::ptz::modify_preset_tour(
+        ptz,
+        &schema::ptz::ModifyPresetTour {
+            profile_token: get_profile_token(device).await?,
+            preset_tour: preset_tour(Some(token.to_string()), tour),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// A tour stored on the camera, as listed by `get_preset_tours`.
+#[derive(Debug, Clone)]
+pub struct NativeTour {
+    pub token: String,
+    pub name: Option<String>,
+    pub state: TourState,
+    /// Preset the camera is at or heading to while touring.
+    pub current_spot: Option<String>,
+    pub auto_start: bool,
+    pub spots: Vec<TourSpot>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TourState {
+    Idle,
+    Touring,
+    Paused,
+    Unknown,
+}
+
+impl From<&schema::onvif::PtzpresetTourState> for TourState {
+    fn from(state: &schema::onvif::PtzpresetTourState) -> Self {
+        match state {
+            schema::onvif::PtzpresetTourState::Idle => Self::Idle,
+            schema::onvif::PtzpresetTourState::Touring => Self::Touring,
+            schema::onvif::PtzpresetTourState::Paused => Self::Paused,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<&schema::onvif::PtzpresetTourSpot> for TourSpot {
+    fn from(spot: &schema::onvif::PtzpresetTourSpot) -> Self {
+        let speed = spot.speed.as_ref();
+        let pan_tilt = speed.and_then(|speed| speed.pan_tilt.as_ref());
+        let zoom = speed.and_then(|speed| speed.zoom.as_ref());
+
+        Self {
+            preset_token: spot
+                .preset_detail
+                .preset_token
+                .as_ref()
+                .map(|token| token.0.clone())
+                .unwrap_or_default(),
+            dwell: spot
+                .stay_time
+                .as_ref()
+                .map(std_duration)
+                .unwrap_or_default(),
+            speed: match (pan_tilt, zoom) {
+                (Some(pan_tilt), Some(zoom)) => Some((pan_tilt.x, pan_tilt.y, zoom.x)),
+                (Some(pan_tilt), None) => Some((pan_tilt.x, pan_tilt.y, 0.0)),
+                (None, Some(zoom)) => Some((0.0, 0.0, zoom.x)),
+                (None, None) => None,
+            },
+        }
+    }
+}
+
+impl From<schema::onvif::PresetTour> for NativeTour {
+    fn from(tour: schema::onvif::PresetTour) -> Self {
+        Self {
+            token: tour.token.map(|token| token.0).unwrap_or_default(),
+            name: tour.name.map(|name| name.0),
+            state: TourState::from(&tour.status.state),
+            current_spot: tour
+                .status
+                .current_tour_spot
+                .as_ref()
+                .and_then(|spot| spot.preset_detail.preset_token.as_ref())
+                .map(|token| token.0.clone()),
+            auto_start: tour.auto_start,
+            spots: tour.tour_spot.iter().map(TourSpot::from).collect(),
+        }
+    }
+}
+
+/// Tours stored on the camera for the cached profile.
+pub async fn get_preset_tours(device: &Device) -> Result<Vec<NativeTour>, DeviceError> {
+    let ptz = &*ptz_with_tours(device).await?;
+
+    let request = &schema::ptz::GetPresetTours {
+        profile_token: get_profile_token(device).await?,
+    };
+    let response = device
+        .retry_policy
+        .run("get_preset_tours", true, || async move {
+            schema::ptz::get_preset_tours(ptz, request)
+                .await
+                .map_err(DeviceError::from)
+        })
+        .await;
+
+    Ok(device
+        .track(response)?
+        .preset_tour
+        .into_iter()
+        .map(NativeTour::from)
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TourOperation {
+    Start,
+    Stop,
+    Pause,
+}
+
+impl From<TourOperation> for schema::onvif::PtzpresetTourOperation {
+    fn from(operation: TourOperation) -> Self {
+        match operation {
+            TourOperation::Start => Self::Start,
+            TourOperation::Stop => Self::Stop,
+            TourOperation::Pause => Self::Pause,
+        }
+    }
+}
+
+impl std::str::FromStr for TourOperation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(Self::Start),
+            "stop" => Ok(Self::Stop),
+            "pause" => Ok(Self::Pause),
+            _ => Err(format!(
+                "unknown tour operation {}, expected start, stop or pause",
+                s
+            )),
+        }
+    }
+}
+
+pub async fn operate_preset_tour(
+    device: &Device,
+    token: &str,
+    operation: TourOperation,
+) -> Result<(), DeviceError> {
+    let ptz = &*ptz_with_tours(device).await?;
+    let operation = schema::onvif::PtzpresetTourOperation::from(operation);
+
+    info!("preset tour {}: {:?}", token, operation);
+    let response = schema::ptz::operate_preset_tour(
+        ptz,
+        &schema::ptz::OperatePresetTour {
+            profile_token: get_profile_token(device).await?,
+            preset_tour_token: schema::onvif::ReferenceToken(token.to_string()),
+            operation,
+        },
+    )
+    .await;
+    device.track(response.map_err(DeviceError::from))?;
+
+    Ok(())
+}
+
+pub async fn start_tour(device: &Device, token: &str) -> Result<(), DeviceError> {
+    operate_preset_tour(device, token, TourOperation::Start).await
+}
+
+pub async fn stop_tour(device: &Device, token: &str) -> Result<(), DeviceError> {
+    operate_preset_tour(device, token, TourOperation::Stop).await
+}
+
+pub async fn pause_tour(device: &Device, token: &str) -> Result<(), DeviceError> {
+    operate_preset_tour(device, token, TourOperation::Pause).await
+}
+
+pub async fn remove_tour(device: &Device, token: &str) -> Result<(), DeviceError> {
+    let ptz = &*ptz_with_tours(device).await?;
+
+    info!("remove preset tour: {}", token);
+    schema::ptz::remove_preset_tour(
+        ptz,
+        &schema::ptz::RemovePresetTour {
+            profile_token: get_profile_token(device).await?,
+            preset_tour_token: schema::onvif::ReferenceToken(token.to_string()),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Guard tour run from this side, for cameras without native tours: go to
+/// each spot's preset at its speed and stay for its dwell, once or until
+/// `cancel` fires. With `wait_for_idle` the dwell starts once the camera
+/// has arrived rather than when the goto returns. `Superseded` means
+/// `cancel` ended it.
+pub async fn run_tour(
+    device: &Device,
+    spots: &[TourSpot],
+    loop_forever: bool,
+    wait_for_idle: bool,
+    cancel: &CancellationToken,
+) -> Result<MotionOutcome, DeviceError> {
+    loop {
+        for spot in spots {
+            if cancel.is_cancelled() {
+                return Ok(MotionOutcome::Superseded);
+            }
+
+            info!("tour: preset {}", spot.preset_token);
+            let preset_token = schema::onvif::ReferenceToken(spot.preset_token.clone());
+            goto_preset(device, preset_token, spot.speed.map(ptz_speed)).await?;
+            if wait_for_idle {
+                let idle = ptz::wait_for_idle(device, ptz::IDLE_POLL_INTERVAL, ptz::IDLE_DEADLINE);
+                let arrived = tokio::select! {
+                    _ = cancel.cancelled() => return Ok(MotionOutcome::Superseded),
+                    status = idle => status,
+                };
+                // A slow camera shouldn't end the tour, dwell anyway.
+                if let Err(e) = arrived {
+                    warn!("tour: preset {}: {}", spot.preset_token, e);
+                }
+            }
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(MotionOutcome::Superseded),
+                _ = tokio::time::sleep(spot.dwell) => {}
+            }
+        }
+
+        if !loop_forever || spots.is_empty() {
+            return Ok(MotionOutcome::Completed);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs::File;
+use std::io::Write;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use onvif::{schema::transport, soap};
+use tracing::{debug_span, warn, Instrument};
+use url::Url;
+
+/// One SOAP round trip, as seen by a `TraceSink`.
+pub struct TraceEvent<'a> {
+    pub service: &'a str,
+    pub operation: &'a str,
+    /// Request body, with any password redacted.
+    pub request: &'a str,
+    pub response: Result<&'a str, &'a transport::Error>,
+    pub elapsed: Duration,
+}
+
+pub trait TraceSink: Send + Sync {
+    fn record(&self, event: &TraceEvent<'_>);
+}
+
+fn format_event(event: &TraceEvent<'_>) -> String {
+    let response = match event.response {
+        Ok(body) => body.to_string(),
+        Err(e) => format!("error: {}", e),
+    };
+    format!(
+        "--> {} {} ({:?})\n{}\n<-- {}\n",
+        event.service, event.operation, event.elapsed, event.request, response
+    )
+}
+
+pub struct StderrSink;
+
+impl TraceSink for StderrSink {
+    fn record(&self, event: &TraceEvent<'_>) {
+        eprint!("{}", format_event(event));
+    }
+}
+
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+}
+
+impl TraceSink for FileSink {
+    fn record(&self, event: &TraceEvent<'_>) {
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(format_event(event).as_bytes()) {
+            warn!("failed to write SOAP trace: {}", e);
+        }
+    }
+}
+
+/// How `ServiceClient` reports a request that ran out of time.
+/// `Transport::request` can only fail with a `transport::Error`, so this
+/// exact text stands in for a timeout variant and `DeviceError::from` turns
+/// it into `DeviceError::Timeout`. It is compared whole, never searched for.
+pub(crate) const TIMED_OUT: &str = "test-ptz: request timed out";
+
+/// A SOAP client for one service. Every call is bounded by `timeout` and
+/// optionally reported to a `TraceSink`.
+#[derive(Clone)]
+pub struct ServiceClient {
+    client: soap::client::Client,
+    /// Unsigned client tried first for read-only calls.
+    anonymous: Option<soap::client::Client>,
+    service: &'static str,
+    uri: Url,
+    timeout: Duration,
+    sink: Option<Arc<dyn TraceSink>>,
+}
+
+impl ServiceClient {
+    pub(crate) fn new(
+        client: soap::client::Client,
+        service: &'static str,
+        uri: Url,
+        timeout: Duration,
+        sink: Option<Arc<dyn TraceSink>>,
+    ) -> Self {
+        Self {
+            client,
+            anonymous: None,
+            service,
+            uri,
+            timeout,
+            sink,
+        }
+    }
+
+    /// Send `message`, giving up after `timeout` even if the camera accepted
+    /// the connection and never answers.
+    async fn send(&self, message: &str) -> Result<String, transport::Error> {
+        let span = debug_span!(
+            "soap",
+            service = self.service,
+            operation = operation_name(message),
+            uri = %self.uri
+        );
+        let request = self.request_signed_if_needed(message).instrument(span);
+        match tokio::time::timeout(self.timeout, request).await {
+            Ok(response) => response,
+            Err(_) => {
+                warn!(
+                    "{} {} timed out after {:?}",
+                    self.service,
+                    operation_name(message),
+                    self.timeout
+                );
+                Err(transport::Error::Protocol(TIMED_OUT.to_string()))
+            }
+        }
+    }
+
+    /// Read-only calls go through the unsigned client first and are only
+    /// signed if the camera rejects them.
+    async fn request_signed_if_needed(&self, message: &str) -> Result<String, transport::Error> {
+        if let Some(ref anonymous) = self.anonymous {
+            if operation_name(message).starts_with("Get") {
+                match transport::Transport::request(anonymous, message).await {
+                    Err(transport::Error::Authorization(_)) => {}
+                    response => return response,
+                }
+            }
+        }
+        transport::Transport::request(&self.client, message).await
+    }
+
+    pub(crate) fn anonymous_first(mut self, anonymous: soap::client::Client) -> Self {
+        self.anonymous = Some(anonymous);
+        self
+    }
+
+    /// This client without the unsigned first attempt, for calls that must
+    /// carry the credentials.
+    pub(crate) fn signed(&self) -> Self {
+        Self {
+            anonymous: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn service(&self) -> &'static str {
+        self.service
+    }
+
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+}
+
+impl Deref for ServiceClient {
+    type Target = soap::client::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl DerefMut for ServiceClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+
+#[async_trait]
+impl transport::Transport for ServiceClient {
+    async fn request(&self, message: &str) -> Result<String, transport::Error> {
+        let sink = match self.sink {
+            Some(ref sink) => sink,
+            None => return self.send(message).await,
+        };
+
+        let start = Instant::now();
+        let response = self.send(message).await;
+        let request = redact(message);
+        sink.record(&TraceEvent {
+            service: self.service,
+            operation: operation_name(message),
+            request: &request,
+            response: response.as_deref(),
+            elapsed: start.elapsed(),
+        });
+        response
+    }
+}
+
+/// Local name of the first element, which is the operation for a request
+/// body.
+fn operation_name(message: &str) -> &str {
+    let tag = message
+        .split('<')
+        .skip(1)
+        .find(|tag| !tag.starts_with('?') && !tag.starts_with('!'))
+        .unwrap_or("unknown");
+    let end = tag
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(tag.len());
+    let name = &tag[..end];
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Blank out the contents of any `Password` element.
+fn redact(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(open) = rest.find("Password") {
+        let (before, after) = rest.split_at(open);
+        out.push_str(before);
+        let (content_start, content_end) = match (after.find('>'), after.find("</")) {
+            (Some(start), Some(end)) if start < end => (start + 1, end),
+            _ => {
+                out.push_str(after);
+                return out;
+            }
+        };
+        let close_end = after[content_end..]
+            .find('>')
+            .map_or(after.len(), |i| content_end + i + 1);
+        out.push_str(&after[..content_start]);
+        out.push_str("<redacted>");
+        out.push_str(&after[content_end..close_end]);
+        rest = &after[close_end..];
+    }
+    out.push_str(rest);
+    out
+}
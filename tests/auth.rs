@@ -0,0 +1,46 @@
+mod common;
+
+use common::MockCamera;
+use test_ptz::ptz::{self, StopAxes};
+use test_ptz::{AuthMode, Device, DeviceError};
+
+#[tokio::test]
+async fn digest_only_camera_with_open_reads_is_signed_with_digest() {
+    let camera = MockCamera::builder()
+        .digest("admin", "secret")
+        .open_reads()
+        .start()
+        .await;
+    let device = Device::new(
+        Some(camera.url.clone()),
+        Some("admin".to_string()),
+        Some("secret".to_string()),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(device.auth_mode(), AuthMode::HttpDigest);
+    // Not a read, so only a signed request gets through.
+    ptz::send_stop_ptz(&device, StopAxes::All, None)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn wrong_password_on_a_camera_with_open_reads_fails_authentication() {
+    let camera = MockCamera::builder()
+        .username_token("admin", "secret")
+        .open_reads()
+        .start()
+        .await;
+    let result = Device::builder()
+        .uri(camera.url.clone())
+        .credentials("admin".to_string(), "wrong".to_string())
+        .build()
+        .await;
+
+    match result {
+        Err(DeviceError::AuthenticationFailed { username }) => assert_eq!(username, "admin"),
+        other => panic!("expected an authentication failure, got {:?}", other),
+    }
+}
@@ -0,0 +1,845 @@
+//! A pretend PTZ camera answering the SOAP calls this crate makes with
+//! canned responses, for the integration tests and `examples/mock_camera.rs`.
+
+// Each test binary uses a different part of it.
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use test_ptz::Device;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use url::Url;
+
+const SPACES: &str = "http://www.onvif.org/ver10/tptz";
+const REALM: &str = "mock camera";
+const NONCE: &str = "3c9e1b2a";
+
+/// Where the pretend camera points, moved by absolute and relative moves
+/// and by going to presets.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub pan: f64,
+    pub tilt: f64,
+    pub zoom: f64,
+}
+
+/// A request the camera received.
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// Local name of the body element, e.g. `ContinuousMove`.
+    pub operation: String,
+    pub body: String,
+    pub at: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct Preset {
+    token: String,
+    name: String,
+    position: Position,
+}
+
+/// What the camera offers and how it misbehaves.
+#[derive(Debug, Clone)]
+pub struct MockOptions {
+    media: bool,
+    media2: bool,
+    max_presets: usize,
+    /// Operations answered with a fault, with its subcode.
+    faults: Vec<(&'static str, &'static str)>,
+    /// Wait before every answer.
+    delay: Duration,
+    /// Read requests but never answer them.
+    silent: bool,
+    log: bool,
+    auth: Auth,
+    open_reads: bool,
+}
+
+/// Which credentials the camera accepts.
+#[derive(Debug, Clone, Copy)]
+enum Auth {
+    Open,
+    UsernameToken(&'static str, &'static str),
+    Digest(&'static str, &'static str),
+}
+
+/// Why a request didn't get past the camera's authentication.
+enum Rejection {
+    /// HTTP 401 with a Digest challenge.
+    Unauthorized,
+    /// A SOAP fault with this subcode.
+    Fault(&'static str),
+}
+
+impl Default for MockOptions {
+    fn default() -> Self {
+        Self {
+            media: true,
+            media2: false,
+            max_presets: 16,
+            faults: vec![],
+            delay: Duration::ZERO,
+            silent: false,
+            log: false,
+            auth: Auth::Open,
+            open_reads: false,
+        }
+    }
+}
+
+impl MockOptions {
+    /// Only the ver20 media service, like some Profile T cameras.
+    pub fn media2_only(mut self) -> Self {
+        self.media = false;
+        self.media2 = true;
+        self
+    }
+
+    pub fn max_presets(mut self, max_presets: usize) -> Self {
+        self.max_presets = max_presets;
+        self
+    }
+
+    /// Answer `operation` with a fault, e.g. `("GetServices",
+    /// "ter:ActionNotSupported")`.
+    pub fn fault(mut self, operation: &'static str, subcode: &'static str) -> Self {
+        self.faults.push((operation, subcode));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Accept connections and read what comes in, but never answer.
+    pub fn silent(mut self) -> Self {
+        self.silent = true;
+        self
+    }
+
+    /// Print every operation as it comes in.
+    pub fn log(mut self) -> Self {
+        self.log = true;
+        self
+    }
+
+    /// Only accept a WS-UsernameToken for `username` and `password`. A wrong
+    /// token gets a `ter:NotAuthorized` fault. GetSystemDateAndTime stays
+    /// open.
+    pub fn username_token(mut self, username: &'static str, password: &'static str) -> Self {
+        self.auth = Auth::UsernameToken(username, password);
+        self
+    }
+
+    /// Only accept HTTP Digest as `username` and `password`, like cameras
+    /// with the UsernameToken turned off. GetSystemDateAndTime stays open.
+    pub fn digest(mut self, username: &'static str, password: &'static str) -> Self {
+        self.auth = Auth::Digest(username, password);
+        self
+    }
+
+    /// With authentication, still answer Get calls that carry no
+    /// credentials.
+    pub fn open_reads(mut self) -> Self {
+        self.open_reads = true;
+        self
+    }
+
+    pub async fn start(self) -> MockCamera {
+        self.bind("127.0.0.1:0")
+            .await
+            .expect("mock camera can listen on localhost")
+    }
+
+    pub async fn bind(self, addr: &str) -> std::io::Result<MockCamera> {
+        let listener = TcpListener::bind(addr).await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?)).unwrap();
+        let state = Arc::new(State {
+            options: self,
+            base: url.as_str().trim_end_matches('/').to_string(),
+            position: Default::default(),
+            presets: Default::default(),
+            calls: Default::default(),
+        });
+
+        let server = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let _ = serve(stream, &state).await;
+                    });
+                }
+            }
+        });
+        Ok(MockCamera { url, state, server })
+    }
+}
+
+struct State {
+    options: MockOptions,
+    base: String,
+    position: Mutex<Position>,
+    presets: Mutex<Vec<Preset>>,
+    calls: Mutex<Vec<Call>>,
+}
+
+/// A camera listening on localhost until dropped.
+pub struct MockCamera {
+    /// Base address to hand to `Device::new`.
+    pub url: Url,
+    state: Arc<State>,
+    server: JoinHandle<()>,
+}
+
+impl MockCamera {
+    pub fn builder() -> MockOptions {
+        MockOptions::default()
+    }
+
+    pub async fn start() -> Self {
+        Self::builder().start().await
+    }
+
+    /// `Device::new` on this camera, without credentials.
+    pub async fn connect(&self) -> Device {
+        Device::new(Some(self.url.clone()), None, None)
+            .await
+            .expect("mock camera connects")
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.calls.lock().unwrap().clone()
+    }
+
+    /// The operations received so far, in order.
+    pub fn operations(&self) -> Vec<String> {
+        self.calls()
+            .into_iter()
+            .map(|call| call.operation)
+            .collect()
+    }
+
+    pub fn count(&self, operation: &str) -> usize {
+        self.calls()
+            .iter()
+            .filter(|call| call.operation == operation)
+            .count()
+    }
+
+    /// The last request for `operation`.
+    pub fn last(&self, operation: &str) -> Option<Call> {
+        self.calls()
+            .into_iter()
+            .rev()
+            .find(|call| call.operation == operation)
+    }
+
+    pub fn position(&self) -> Position {
+        *self.state.position.lock().unwrap()
+    }
+
+    pub fn set_position(&self, position: Position) {
+        *self.state.position.lock().unwrap() = position;
+    }
+}
+
+impl Drop for MockCamera {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// Answer the requests on one connection until the client closes it.
+async fn serve(mut stream: TcpStream, state: &State) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+    loop {
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let head_end = match find(&buffer, b"\r\n\r\n") {
+            Some(end) => end + 4,
+            None => continue,
+        };
+        let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+        let length = content_length(&head);
+        if buffer.len() < head_end + length {
+            continue;
+        }
+
+        let body = String::from_utf8_lossy(&buffer[head_end..head_end + length]).to_string();
+        buffer.drain(..head_end + length);
+
+        let operation = operation(&body).to_string();
+        if state.options.log {
+            println!("{}", operation);
+        }
+        state.calls.lock().unwrap().push(Call {
+            operation: operation.clone(),
+            body: body.clone(),
+            at: Instant::now(),
+        });
+        if state.options.silent {
+            continue;
+        }
+        tokio::time::sleep(state.options.delay).await;
+
+        let answer = match authenticate(&head, &body, &operation, &state.options) {
+            Ok(()) => respond(&operation, &body, state),
+            Err(Rejection::Fault(subcode)) => Err(subcode),
+            Err(Rejection::Unauthorized) => {
+                let reply = format!(
+                    "HTTP/1.1 401 Unauthorized\r\n\
+                     WWW-Authenticate: Digest realm=\"{}\", qop=\"auth\", nonce=\"{}\", algorithm=MD5\r\n\
+                     Content-Length: 0\r\n\r\n",
+                    REALM, NONCE
+                );
+                stream.write_all(reply.as_bytes()).await?;
+                continue;
+            }
+        };
+        let (status, response) = match answer {
+            Ok(response) => ("200 OK", envelope(&response)),
+            Err(subcode) => (
+                "500 Internal Server Error",
+                envelope(&fault(&operation, subcode)),
+            ),
+        };
+        let reply = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/soap+xml; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            response.len(),
+            response
+        );
+        stream.write_all(reply.as_bytes()).await?;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn content_length(head: &str) -> usize {
+    header(head, "content-length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Check the credentials of a request against `MockOptions::auth`.
+fn authenticate(
+    head: &str,
+    body: &str,
+    operation: &str,
+    options: &MockOptions,
+) -> Result<(), Rejection> {
+    let open =
+        operation == "GetSystemDateAndTime" || (options.open_reads && operation.starts_with("Get"));
+    let token = body.contains("UsernameToken");
+    match options.auth {
+        Auth::Open => Ok(()),
+        Auth::UsernameToken(username, password) if token => {
+            if token_matches(body, username, password) {
+                Ok(())
+            } else {
+                Err(Rejection::Fault("ter:NotAuthorized"))
+            }
+        }
+        Auth::Digest(username, password) => match header(head, "authorization") {
+            Some(authorization) if digest_matches(authorization, username, password) => Ok(()),
+            Some(_) => Err(Rejection::Unauthorized),
+            // A UsernameToken this camera doesn't check.
+            None if token => Err(Rejection::Unauthorized),
+            None if open => Ok(()),
+            None => Err(Rejection::Unauthorized),
+        },
+        _ if open => Ok(()),
+        Auth::UsernameToken(..) => Err(Rejection::Fault("ter:NotAuthorized")),
+    }
+}
+
+/// Check a WS-UsernameToken's PasswordDigest, the Base64 SHA-1 of nonce,
+/// creation time and password.
+fn token_matches(body: &str, username: &str, password: &str) -> bool {
+    let fields = (
+        inner_text(body, "Username"),
+        inner_text(body, "Password"),
+        inner_text(body, "Nonce").and_then(|nonce| base64::decode(nonce).ok()),
+        inner_text(body, "Created"),
+    );
+    let (user, digest, nonce, created) = match fields {
+        (Some(user), Some(digest), Some(nonce), Some(created)) => (user, digest, nonce, created),
+        _ => return false,
+    };
+    let mut sha1 = Sha1::new();
+    sha1.update(&nonce);
+    sha1.update(created.as_bytes());
+    sha1.update(password.as_bytes());
+    user == username && digest == base64::encode(sha1.digest().bytes())
+}
+
+/// Text of the first element named `element`, with or without a prefix or
+/// attributes.
+fn inner_text<'a>(body: &'a str, element: &str) -> Option<&'a str> {
+    let (start, _) = body.match_indices(element).find(|&(at, _)| {
+        matches!(body[..at].chars().last(), Some('<') | Some(':'))
+            && matches!(
+                body[at + element.len()..].chars().next(),
+                Some(' ') | Some('>')
+            )
+    })?;
+    let content = start + body[start..].find('>')? + 1;
+    let end = content + body[content..].find('<')?;
+    Some(&body[content..end])
+}
+
+/// Check a Digest `Authorization` header against RFC 7616 with MD5.
+fn digest_matches(authorization: &str, username: &str, password: &str) -> bool {
+    let fields = match authorization.strip_prefix("Digest ") {
+        Some(fields) => fields,
+        None => return false,
+    };
+    let field = |name: &str| {
+        fields.split(',').find_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            if key.trim() == name {
+                Some(value.trim().trim_matches('"').to_string())
+            } else {
+                None
+            }
+        })
+    };
+    let md5 = |text: String| format!("{:x}", Md5::digest(text.as_bytes()));
+
+    let (user, uri, response) = match (field("username"), field("uri"), field("response")) {
+        (Some(user), Some(uri), Some(response)) => (user, uri, response),
+        _ => return false,
+    };
+    let ha1 = md5(format!("{}:{}:{}", username, REALM, password));
+    let ha2 = md5(format!("POST:{}", uri));
+    let expected = match (field("qop"), field("nc"), field("cnonce")) {
+        (Some(qop), Some(nc), Some(cnonce)) => md5(format!(
+            "{}:{}:{}:{}:{}:{}",
+            ha1, NONCE, nc, cnonce, qop, ha2
+        )),
+        _ => md5(format!("{}:{}:{}", ha1, NONCE, ha2)),
+    };
+    user == username && response == expected
+}
+
+/// Local name of the first element in the SOAP body.
+fn operation(body: &str) -> &str {
+    let rest = match body.find("Body") {
+        Some(start) => &body[start..],
+        None => return "unknown",
+    };
+    let tag = match rest.split('<').nth(1) {
+        Some(tag) => tag,
+        None => return "unknown",
+    };
+    let end = tag
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(tag.len());
+    let name = &tag[..end];
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Value of the first `attribute` on an element named `element`.
+pub fn attribute(body: &str, element: &str, attribute: &str) -> Option<f64> {
+    let start = body.find(&format!(":{} ", element))?;
+    let tag = &body[start..start + body[start..].find('>')?];
+    let value_start = tag.find(&format!("{}=\"", attribute))? + attribute.len() + 2;
+    let value_end = value_start + tag[value_start..].find('"')?;
+    tag[value_start..value_end].parse().ok()
+}
+
+/// Text of the first element named `element`.
+pub fn text(body: &str, element: &str) -> Option<String> {
+    let start = body.find(&format!(":{}>", element))? + element.len() + 2;
+    let end = start + body[start..].find('<')?;
+    Some(body[start..end].to_string())
+}
+
+fn respond(operation: &str, body: &str, state: &State) -> Result<String, &'static str> {
+    if let Some(&(_, subcode)) = state
+        .options
+        .faults
+        .iter()
+        .find(|(faulty, _)| *faulty == operation)
+    {
+        return Err(subcode);
+    }
+
+    let base = &state.base;
+    let media2 = body.contains("ver20/media/wsdl");
+    let response = match operation {
+        "GetSystemDateAndTime" => {
+            let now = chrono::Utc::now();
+            format!(
+                "<tds:GetSystemDateAndTimeResponse><tds:SystemDateAndTime>\
+                 <tt:DateTimeType>Manual</tt:DateTimeType>\
+                 <tt:DaylightSavings>false</tt:DaylightSavings>\
+                 <tt:UTCDateTime>\
+                 <tt:Time><tt:Hour>{}</tt:Hour><tt:Minute>{}</tt:Minute><tt:Second>{}</tt:Second></tt:Time>\
+                 <tt:Date><tt:Year>{}</tt:Year><tt:Month>{}</tt:Month><tt:Day>{}</tt:Day></tt:Date>\
+                 </tt:UTCDateTime>\
+                 </tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>",
+                now.format("%-H"),
+                now.format("%-M"),
+                now.format("%-S"),
+                now.format("%Y"),
+                now.format("%-m"),
+                now.format("%-d"),
+            )
+        }
+        "GetDeviceInformation" => "<tds:GetDeviceInformationResponse>\
+             <tds:Manufacturer>Mock</tds:Manufacturer>\
+             <tds:Model>PTZ-1</tds:Model>\
+             <tds:FirmwareVersion>1.0</tds:FirmwareVersion>\
+             <tds:SerialNumber>0001</tds:SerialNumber>\
+             <tds:HardwareId>1</tds:HardwareId>\
+             </tds:GetDeviceInformationResponse>"
+            .to_string(),
+        "GetServices" => {
+            let service = |namespace: &str, path: &str| {
+                format!(
+                    "<tds:Service><tds:Namespace>{}</tds:Namespace>\
+                     <tds:XAddr>{}/onvif/{}</tds:XAddr>\
+                     <tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version>\
+                     </tds:Service>",
+                    namespace, base, path
+                )
+            };
+            let mut services =
+                service("http://www.onvif.org/ver10/device/wsdl", "device_service");
+            if state.options.media {
+                services += &service("http://www.onvif.org/ver10/media/wsdl", "media_service");
+            }
+            if state.options.media2 {
+                services += &service("http://www.onvif.org/ver20/media/wsdl", "media2_service");
+            }
+            services += &service("http://www.onvif.org/ver20/ptz/wsdl", "ptz_service");
+            format!("<tds:GetServicesResponse>{}</tds:GetServicesResponse>", services)
+        }
+        "GetCapabilities" => {
+            let mut capabilities = format!(
+                "<tt:Device><tt:XAddr>{}/onvif/device_service</tt:XAddr></tt:Device>",
+                base
+            );
+            if state.options.media {
+                capabilities += &format!(
+                    "<tt:Media><tt:XAddr>{}/onvif/media_service</tt:XAddr>\
+                     <tt:StreamingCapabilities><tt:RTPMulticast>false</tt:RTPMulticast>\
+                     <tt:RTP_TCP>true</tt:RTP_TCP><tt:RTP_RTSP_TCP>true</tt:RTP_RTSP_TCP>\
+                     </tt:StreamingCapabilities></tt:Media>",
+                    base
+                );
+            }
+            capabilities += &format!(
+                "<tt:PTZ><tt:XAddr>{}/onvif/ptz_service</tt:XAddr></tt:PTZ>",
+                base
+            );
+            format!(
+                "<tds:GetCapabilitiesResponse><tds:Capabilities>{}</tds:Capabilities>\
+                 </tds:GetCapabilitiesResponse>",
+                capabilities
+            )
+        }
+        "GetServiceCapabilities" if body.contains("ver20/ptz/wsdl") => {
+            "<tptz:GetServiceCapabilitiesResponse>\
+             <tptz:Capabilities EFlip=\"false\" Reverse=\"false\" MoveStatus=\"true\" StatusPosition=\"true\"/>\
+             </tptz:GetServiceCapabilitiesResponse>"
+                .to_string()
+        }
+        "GetServiceCapabilities" => "<trt:GetServiceCapabilitiesResponse>\
+             <trt:Capabilities SnapshotUri=\"true\" Rotation=\"false\" OSD=\"false\">\
+             <trt:ProfileCapabilities MaximumNumberOfProfiles=\"1\"/>\
+             <trt:StreamingCapabilities RTPMulticast=\"false\" RTP_TCP=\"true\" RTP_RTSP_TCP=\"true\"/>\
+             </trt:Capabilities>\
+             </trt:GetServiceCapabilitiesResponse>"
+            .to_string(),
+        "GetProfiles" if media2 => "<tr2:GetProfilesResponse>\
+             <tr2:Profiles token=\"profile_2\" fixed=\"true\"><tr2:Name>main</tr2:Name></tr2:Profiles>\
+             </tr2:GetProfilesResponse>"
+            .to_string(),
+        "GetProfiles" => "<trt:GetProfilesResponse>\
+             <trt:Profiles token=\"profile_1\" fixed=\"true\">\
+             <tt:Name>main</tt:Name>\
+             <tt:PTZConfiguration token=\"ptz_config_1\">\
+             <tt:Name>ptz</tt:Name><tt:UseCount>1</tt:UseCount><tt:NodeToken>node_1</tt:NodeToken>\
+             </tt:PTZConfiguration>\
+             </trt:Profiles>\
+             </trt:GetProfilesResponse>"
+            .to_string(),
+        "GetStreamUri" if media2 => format!(
+            "<tr2:GetStreamUriResponse><tr2:Uri>rtsp://{}/main</tr2:Uri></tr2:GetStreamUriResponse>",
+            base.trim_start_matches("http://")
+        ),
+        "GetNodes" => format!(
+            "<tptz:GetNodesResponse>{}</tptz:GetNodesResponse>",
+            node("PTZNode", state.options.max_presets)
+        ),
+        "GetNode" => format!(
+            "<tptz:GetNodeResponse>{}</tptz:GetNodeResponse>",
+            node("PTZNode", state.options.max_presets)
+        ),
+        "GetConfigurationOptions" => format!(
+            "<tptz:GetConfigurationOptionsResponse><tptz:PTZConfigurationOptions>\
+             <tt:Spaces>{}</tt:Spaces>\
+             <tt:PTZTimeout><tt:Min>PT1S</tt:Min><tt:Max>PT60S</tt:Max></tt:PTZTimeout>\
+             </tptz:PTZConfigurationOptions></tptz:GetConfigurationOptionsResponse>",
+            spaces()
+        ),
+        "GetConfigurations" => "<tptz:GetConfigurationsResponse>\
+             <tptz:PTZConfiguration token=\"ptz_config_1\">\
+             <tt:Name>ptz</tt:Name><tt:UseCount>1</tt:UseCount><tt:NodeToken>node_1</tt:NodeToken>\
+             </tptz:PTZConfiguration>\
+             </tptz:GetConfigurationsResponse>"
+            .to_string(),
+        "GetStatus" => {
+            let position = *state.position.lock().unwrap();
+            format!(
+                "<tptz:GetStatusResponse><tptz:PTZStatus>\
+                 <tt:Position>{}</tt:Position>\
+                 <tt:MoveStatus><tt:PanTilt>IDLE</tt:PanTilt><tt:Zoom>IDLE</tt:Zoom></tt:MoveStatus>\
+                 <tt:UtcTime>{}</tt:UtcTime>\
+                 </tptz:PTZStatus></tptz:GetStatusResponse>",
+                vector(position),
+                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            )
+        }
+        "GetPresets" => {
+            let presets: String = state
+                .presets
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|preset| {
+                    format!(
+                        "<tptz:Preset token=\"{}\"><tt:Name>{}</tt:Name>\
+                         <tt:PTZPosition>{}</tt:PTZPosition></tptz:Preset>",
+                        preset.token,
+                        preset.name,
+                        vector(preset.position)
+                    )
+                })
+                .collect();
+            format!("<tptz:GetPresetsResponse>{}</tptz:GetPresetsResponse>", presets)
+        }
+        "SetPreset" => {
+            let name = text(body, "PresetName").unwrap_or_default();
+            let position = *state.position.lock().unwrap();
+            let mut presets = state.presets.lock().unwrap();
+            let token = match text(body, "PresetToken") {
+                Some(token) => {
+                    let preset = presets
+                        .iter_mut()
+                        .find(|preset| preset.token == token)
+                        .ok_or("ter:NoToken")?;
+                    preset.name = name;
+                    preset.position = position;
+                    token
+                }
+                None if presets.len() >= state.options.max_presets => {
+                    return Err("ter:TooManyPresets")
+                }
+                None => {
+                    let token = format!("preset_{}", presets.len() + 1);
+                    presets.push(Preset {
+                        token: token.clone(),
+                        name,
+                        position,
+                    });
+                    token
+                }
+            };
+            format!(
+                "<tptz:SetPresetResponse><tptz:PresetToken>{}</tptz:PresetToken></tptz:SetPresetResponse>",
+                token
+            )
+        }
+        "GotoPreset" => {
+            let token = text(body, "PresetToken").unwrap_or_default();
+            let position = state
+                .presets
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|preset| preset.token == token)
+                .ok_or("ter:NoToken")?
+                .position;
+            *state.position.lock().unwrap() = position;
+            "<tptz:GotoPresetResponse/>".to_string()
+        }
+        "RemovePreset" => {
+            let token = text(body, "PresetToken").unwrap_or_default();
+            let mut presets = state.presets.lock().unwrap();
+            let index = presets
+                .iter()
+                .position(|preset| preset.token == token)
+                .ok_or("ter:NoToken")?;
+            presets.remove(index);
+            "<tptz:RemovePresetResponse/>".to_string()
+        }
+        "AbsoluteMove" => {
+            let mut position = state.position.lock().unwrap();
+            position.pan = attribute(body, "PanTilt", "x").unwrap_or(position.pan);
+            position.tilt = attribute(body, "PanTilt", "y").unwrap_or(position.tilt);
+            position.zoom = attribute(body, "Zoom", "x").unwrap_or(position.zoom);
+            "<tptz:AbsoluteMoveResponse/>".to_string()
+        }
+        "RelativeMove" => {
+            let mut position = state.position.lock().unwrap();
+            let clamp = |value: f64, min: f64| value.clamp(min, 1.0);
+            position.pan = clamp(
+                position.pan + attribute(body, "PanTilt", "x").unwrap_or(0.0),
+                -1.0,
+            );
+            position.tilt = clamp(
+                position.tilt + attribute(body, "PanTilt", "y").unwrap_or(0.0),
+                -1.0,
+            );
+            position.zoom = clamp(
+                position.zoom + attribute(body, "Zoom", "x").unwrap_or(0.0),
+                0.0,
+            );
+            "<tptz:RelativeMoveResponse/>".to_string()
+        }
+        "ContinuousMove" => "<tptz:ContinuousMoveResponse/>".to_string(),
+        "Stop" => "<tptz:StopResponse/>".to_string(),
+        "GotoHomePosition" => "<tptz:GotoHomePositionResponse/>".to_string(),
+        _ => return Err("ter:ActionNotSupported"),
+    };
+    Ok(response)
+}
+
+fn vector(position: Position) -> String {
+    format!(
+        "<tt:PanTilt x=\"{}\" y=\"{}\" space=\"{}/PanTiltSpaces/PositionGenericSpace\"/>\
+         <tt:Zoom x=\"{}\" space=\"{}/ZoomSpaces/PositionGenericSpace\"/>",
+        position.pan, position.tilt, SPACES, position.zoom, SPACES,
+    )
+}
+
+/// The generic spaces, advertised by the node and the configuration
+/// options alike.
+fn spaces() -> String {
+    let space = |name: &str, path: &str, y: bool, min: f64| {
+        let y_range = if y {
+            "<tt:YRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:YRange>"
+        } else {
+            ""
+        };
+        format!(
+            "<tt:{name}><tt:URI>{spaces}/{path}</tt:URI>\
+             <tt:XRange><tt:Min>{min}</tt:Min><tt:Max>1</tt:Max></tt:XRange>{y_range}</tt:{name}>",
+            name = name,
+            spaces = SPACES,
+            path = path,
+            min = min,
+            y_range = y_range,
+        )
+    };
+    [
+        space(
+            "AbsolutePanTiltPositionSpace",
+            "PanTiltSpaces/PositionGenericSpace",
+            true,
+            -1.0,
+        ),
+        space(
+            "AbsoluteZoomPositionSpace",
+            "ZoomSpaces/PositionGenericSpace",
+            false,
+            0.0,
+        ),
+        space(
+            "RelativePanTiltTranslationSpace",
+            "PanTiltSpaces/TranslationGenericSpace",
+            true,
+            -1.0,
+        ),
+        space(
+            "RelativeZoomTranslationSpace",
+            "ZoomSpaces/TranslationGenericSpace",
+            false,
+            -1.0,
+        ),
+        space(
+            "ContinuousPanTiltVelocitySpace",
+            "PanTiltSpaces/VelocityGenericSpace",
+            true,
+            -1.0,
+        ),
+        space(
+            "ContinuousZoomVelocitySpace",
+            "ZoomSpaces/VelocityGenericSpace",
+            false,
+            -1.0,
+        ),
+    ]
+    .concat()
+}
+
+/// A node with the generic spaces.
+fn node(element: &str, max_presets: usize) -> String {
+    format!(
+        "<tptz:{element} token=\"node_1\" FixedHomePosition=\"false\">\
+         <tt:Name>mock</tt:Name>\
+         <tt:SupportedPTZSpaces>{spaces}</tt:SupportedPTZSpaces>\
+         <tt:MaximumNumberOfPresets>{max_presets}</tt:MaximumNumberOfPresets>\
+         <tt:HomeSupported>true</tt:HomeSupported>\
+         </tptz:{element}>",
+        element = element,
+        spaces = spaces(),
+        max_presets = max_presets,
+    )
+}
+
+fn fault(operation: &str, subcode: &str) -> String {
+    format!(
+        "<s:Fault><s:Code><s:Value>s:Receiver</s:Value>\
+         <s:Subcode><s:Value>{}</s:Value></s:Subcode></s:Code>\
+         <s:Reason><s:Text xml:lang=\"en\">{} failed</s:Text></s:Reason></s:Fault>",
+        subcode, operation
+    )
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+         xmlns:tt=\"http://www.onvif.org/ver10/schema\" \
+         xmlns:tds=\"http://www.onvif.org/ver10/device/wsdl\" \
+         xmlns:trt=\"http://www.onvif.org/ver10/media/wsdl\" \
+         xmlns:tr2=\"http://www.onvif.org/ver20/media/wsdl\" \
+         xmlns:tptz=\"http://www.onvif.org/ver20/ptz/wsdl\" \
+         xmlns:ter=\"http://www.onvif.org/ver10/error\">\
+         <s:Body>{}</s:Body></s:Envelope>",
+        body
+    )
+}
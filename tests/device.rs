@@ -0,0 +1,183 @@
+mod common;
+
+use common::{attribute, text, MockCamera, Position};
+use test_ptz::ptz::{self, StopAxes};
+use test_ptz::{media, DiscoveryPath};
+
+#[tokio::test]
+async fn new_discovers_the_services() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    assert_eq!(device.discovery_path(), DiscoveryPath::GetServices);
+    assert!(device.media_client().is_ok());
+    assert!(device.ptz_client().is_ok());
+    assert!(device.media2_client().is_err());
+    assert_eq!(
+        device.ptz_client().unwrap().uri().as_str(),
+        camera.url.join("onvif/ptz_service").unwrap().as_str()
+    );
+    assert_eq!(device.info().await.unwrap().model, "PTZ-1");
+    assert_eq!(camera.count("GetServices"), 1);
+}
+
+#[tokio::test]
+async fn profile_token_is_fetched_once() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+    let before = camera.count("GetProfiles");
+
+    assert_eq!(
+        media::get_profile_token(&device).await.unwrap().0,
+        "profile_1"
+    );
+    assert_eq!(
+        media::get_profile_token(&device).await.unwrap().0,
+        "profile_1"
+    );
+    assert!(camera.count("GetProfiles") <= before + 1);
+
+    device.refresh_profiles().await.unwrap();
+    assert_eq!(
+        media::get_profile_token(&device).await.unwrap().0,
+        "profile_1"
+    );
+}
+
+#[tokio::test]
+async fn continuous_move_and_stop_reach_the_camera() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    ptz::send_continuous_ptz(&device, 0.5, -0.25, 0.0, None, false, None)
+        .await
+        .unwrap();
+    let moved = camera.last("ContinuousMove").unwrap();
+    assert_eq!(
+        text(&moved.body, "ProfileToken").as_deref(),
+        Some("profile_1")
+    );
+    assert_eq!(attribute(&moved.body, "PanTilt", "x"), Some(0.5));
+    assert_eq!(attribute(&moved.body, "PanTilt", "y"), Some(-0.25));
+
+    ptz::send_stop_ptz(&device, StopAxes::All, None)
+        .await
+        .unwrap();
+    assert_eq!(camera.operations().last().unwrap(), "Stop");
+}
+
+#[tokio::test]
+async fn absolute_and_relative_moves_position_the_camera() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    ptz::send_absolute_ptz(&device, 0.5, 0.5, 0.2, None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        camera.position(),
+        Position {
+            pan: 0.5,
+            tilt: 0.5,
+            zoom: 0.2
+        }
+    );
+
+    ptz::send_relative_ptz(&device, -0.25, 0.0, 0.0, None, None)
+        .await
+        .unwrap();
+    assert_eq!(camera.position().pan, 0.25);
+    assert_eq!(camera.position().tilt, 0.5);
+
+    let status = ptz::get_ptz_status(&device).await.unwrap();
+    assert_eq!((status.pan, status.tilt), (Some(0.25), Some(0.5)));
+}
+
+#[tokio::test]
+async fn out_of_range_moves_are_clamped_before_sending() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    ptz::send_continuous_ptz(&device, 3.0, 0.0, 0.0, None, false, None)
+        .await
+        .unwrap();
+    let moved = camera.last("ContinuousMove").unwrap();
+    assert_eq!(attribute(&moved.body, "PanTilt", "x"), Some(1.0));
+}
+
+#[tokio::test]
+async fn timed_continuous_move_ends_with_a_stop() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    ptz::continuous_move_for(
+        &device,
+        0.5,
+        0.0,
+        0.0,
+        std::time::Duration::from_millis(100),
+    )
+    .await
+    .unwrap();
+    let moves: Vec<_> = camera
+        .operations()
+        .into_iter()
+        .filter(|operation| operation == "ContinuousMove" || operation == "Stop")
+        .collect();
+    assert_eq!(moves, ["ContinuousMove", "Stop"]);
+}
+
+#[tokio::test]
+async fn falls_back_to_get_capabilities_when_get_services_faults() {
+    let camera = MockCamera::builder()
+        .fault("GetServices", "ter:ActionNotSupported")
+        .start()
+        .await;
+    let device = camera.connect().await;
+
+    assert_eq!(device.discovery_path(), DiscoveryPath::GetCapabilities);
+    assert_eq!(camera.count("GetCapabilities"), 1);
+    assert!(device.media_client().is_ok());
+    assert_eq!(
+        device.ptz_client().unwrap().uri().as_str(),
+        camera.url.join("onvif/ptz_service").unwrap().as_str()
+    );
+
+    ptz::send_continuous_ptz(&device, 0.5, 0.0, 0.0, None, false, None)
+        .await
+        .unwrap();
+    assert!(camera.last("ContinuousMove").is_some());
+}
+
+#[tokio::test]
+async fn media2_only_camera_uses_its_profiles() {
+    let camera = MockCamera::builder().media2_only().start().await;
+    let device = camera.connect().await;
+
+    assert!(device.media_client().is_err());
+    assert!(device.media2_client().is_ok());
+    assert_eq!(
+        media::get_profile_token(&device).await.unwrap().0,
+        "profile_2"
+    );
+
+    let uri = media::get_stream_uri(&device, None).await.unwrap();
+    assert_eq!(
+        uri.as_str(),
+        format!("rtsp://127.0.0.1:{}/main", camera.url.port().unwrap())
+    );
+    assert!(camera
+        .last("GetStreamUri")
+        .unwrap()
+        .body
+        .contains("ver20/media/wsdl"));
+
+    ptz::send_continuous_ptz(&device, 0.5, 0.0, 0.0, None, false, None)
+        .await
+        .unwrap();
+    let moved = camera.last("ContinuousMove").unwrap();
+    assert_eq!(
+        text(&moved.body, "ProfileToken").as_deref(),
+        Some("profile_2")
+    );
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::MockCamera;
+use test_ptz::{Device, DeviceManager};
+
+const CAMERAS: usize = 8;
+const DELAY: Duration = Duration::from_millis(100);
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn connects_and_commands_cameras_in_parallel() {
+    let mut cameras = vec![];
+    for _ in 0..CAMERAS {
+        cameras.push(MockCamera::builder().delay(DELAY).start().await);
+    }
+    // A port nobody listens on.
+    let dead = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        url::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap()
+    };
+
+    let mut builders: Vec<_> = cameras
+        .iter()
+        .enumerate()
+        .map(|(i, camera)| {
+            (
+                format!("camera-{}", i),
+                Device::builder()
+                    .uri(camera.url.clone())
+                    .timeout(Duration::from_secs(2)),
+            )
+        })
+        .collect();
+    builders.push((
+        "dead".to_string(),
+        Device::builder().uri(dead).timeout(Duration::from_secs(2)),
+    ));
+
+    let started = Instant::now();
+    let (manager, failures) = DeviceManager::connect_all(builders).await;
+    let elapsed = started.elapsed();
+
+    assert_eq!(manager.names().len(), CAMERAS);
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, "dead");
+
+    // Each connection makes several delayed round trips, one after the
+    // other they would take several times as long.
+    let requests = cameras[0].calls().len() as u32;
+    assert!(requests >= 2);
+    let serial = DELAY * requests * CAMERAS as u32;
+    assert!(
+        elapsed < serial / 2,
+        "connecting took {:?}, one by one would be {:?}",
+        elapsed,
+        serial
+    );
+
+    let started = Instant::now();
+    let results = manager.stop_all().await;
+    assert!(started.elapsed() < DELAY * CAMERAS as u32);
+    assert_eq!(results.len(), CAMERAS);
+    for (name, result) in results {
+        assert!(result.is_ok(), "{}: {:?}", name, result);
+    }
+    for camera in &cameras {
+        assert_eq!(camera.count("Stop"), 1);
+    }
+}
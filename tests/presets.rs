@@ -0,0 +1,56 @@
+mod common;
+
+use common::{MockCamera, Position};
+use test_ptz::presets;
+use test_ptz::DeviceError;
+
+const PORCH: Position = Position {
+    pan: 0.25,
+    tilt: -0.5,
+    zoom: 0.1,
+};
+
+#[tokio::test]
+async fn presets_round_trip() {
+    let camera = MockCamera::start().await;
+    let device = camera.connect().await;
+
+    camera.set_position(PORCH);
+    let token = presets::set_preset(&device, "porch").await.unwrap();
+    let listed = presets::list_presets(&device).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].token, token.0);
+    assert_eq!(listed[0].name.as_deref(), Some("porch"));
+    assert_eq!(listed[0].pan, Some(PORCH.pan));
+
+    camera.set_position(Position::default());
+    presets::goto_preset(&device, token.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(camera.position(), PORCH);
+
+    // Saving under the same name overwrites the preset instead of adding one.
+    camera.set_position(Position::default());
+    let again = presets::set_preset(&device, "porch").await.unwrap();
+    assert_eq!(again.0, token.0);
+    assert_eq!(presets::list_presets(&device).await.unwrap().len(), 1);
+    assert!(camera.last("SetPreset").unwrap().body.contains(&token.0));
+
+    presets::remove_preset(&device, token).await.unwrap();
+    assert!(presets::list_presets(&device).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn a_full_camera_reports_too_many_presets() {
+    let camera = MockCamera::builder().max_presets(1).start().await;
+    let device = camera.connect().await;
+
+    presets::set_preset(&device, "porch").await.unwrap();
+    // Updating the one there is still fine.
+    presets::set_preset(&device, "porch").await.unwrap();
+    assert!(matches!(
+        presets::set_preset(&device, "gate").await,
+        Err(DeviceError::TooManyPresets)
+    ));
+    assert_eq!(presets::list_presets(&device).await.unwrap().len(), 1);
+}
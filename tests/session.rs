@@ -0,0 +1,46 @@
+mod common;
+
+use std::time::Duration;
+
+use common::MockCamera;
+use test_ptz::ptz::{MoveStrategy, RecenterConfig};
+use test_ptz::session::MotionOutcome;
+use test_ptz::Device;
+
+#[tokio::test]
+async fn a_second_recenter_supersedes_the_first() {
+    let camera = MockCamera::start().await;
+    let device = Device::builder()
+        .uri(camera.url.clone())
+        .move_strategy(Some(MoveStrategy::Continuous))
+        .build()
+        .await
+        .unwrap();
+    let config = RecenterConfig::default();
+
+    // A click at the right edge moves for 500ms, the second click comes
+    // in well before that.
+    let (first, second) = tokio::join!(
+        device.ptz_session().recenter(&config, 640, 0, 640, 480),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            device
+                .ptz_session()
+                .recenter(&config, 640, 0, 640, 480)
+                .await
+        }
+    );
+    assert_eq!(first.unwrap(), MotionOutcome::Superseded);
+    assert_eq!(second.unwrap(), MotionOutcome::Completed);
+
+    // The first sequence stops before the second one moves, and only the
+    // second one's move is left running until its own stop.
+    let moves: Vec<_> = camera
+        .operations()
+        .into_iter()
+        .filter(|operation| operation == "ContinuousMove" || operation == "Stop")
+        .collect();
+    assert_eq!(moves.iter().filter(|m| *m == "ContinuousMove").count(), 2);
+    assert_eq!(moves[0], "ContinuousMove");
+    assert_eq!(moves[moves.len() - 2..], ["ContinuousMove", "Stop"]);
+}
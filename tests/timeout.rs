@@ -0,0 +1,26 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::MockCamera;
+use test_ptz::{Device, DeviceError};
+
+#[tokio::test]
+async fn a_camera_that_never_answers_times_out() {
+    let camera = MockCamera::builder().silent().start().await;
+
+    let started = Instant::now();
+    let result = Device::builder()
+        .uri(camera.url.clone())
+        .timeout(Duration::from_millis(300))
+        .build()
+        .await;
+
+    match result {
+        Err(DeviceError::Timeout) => {}
+        Err(e) => panic!("expected a timeout, got {:?}", e),
+        Ok(_) => panic!("expected a timeout, connected"),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(camera.count("GetServices") >= 1);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::MockCamera;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_native_tls::native_tls;
+use url::Url;
+
+use test_ptz::{Device, DeviceError};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/certs")
+        .join(name)
+}
+
+/// HTTPS in front of `camera` with the self-signed certificate in
+/// `tests/certs`, as a TLS terminator would put it.
+async fn https_proxy(camera: &MockCamera) -> (Url, tokio::task::JoinHandle<()>) {
+    let cert = std::fs::read(fixture("cert.pem")).unwrap();
+    let key = std::fs::read(fixture("key.pem")).unwrap();
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).unwrap();
+    let acceptor =
+        tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = Url::parse(&format!("https://{}/", listener.local_addr().unwrap())).unwrap();
+    let upstream = camera.url.socket_addrs(|| None).unwrap()[0];
+    let proxy = tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                // Clients that reject the certificate hang up mid-handshake.
+                let mut client = match acceptor.accept(stream).await {
+                    Ok(client) => client,
+                    Err(_) => return,
+                };
+                let mut camera = TcpStream::connect(upstream).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut camera).await;
+            });
+        }
+    });
+    (url, proxy)
+}
+
+fn builder(url: &Url) -> test_ptz::DeviceBuilder {
+    Device::builder()
+        .uri(url.clone())
+        .timeout(Duration::from_secs(2))
+}
+
+#[tokio::test]
+async fn untrusted_certificate_is_a_tls_error() {
+    let camera = MockCamera::start().await;
+    let (url, _proxy) = https_proxy(&camera).await;
+
+    let result = builder(&url).build().await;
+    assert!(
+        matches!(result, Err(DeviceError::Tls(_))),
+        "expected a TLS error, got {:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn trusted_ca_certificate_connects() {
+    let camera = MockCamera::start().await;
+    let (url, _proxy) = https_proxy(&camera).await;
+
+    let device = builder(&url)
+        .ca_certificate(fixture("cert.pem"))
+        .build()
+        .await
+        .unwrap();
+    // The camera advertises its plain http services, which are followed
+    // onto the https address.
+    assert_eq!(device.ptz_client().unwrap().uri().scheme(), "https");
+    assert_eq!(device.ptz_client().unwrap().uri().port(), url.port());
+    test_ptz::ptz::send_stop_ptz(&device, test_ptz::ptz::StopAxes::All, None)
+        .await
+        .unwrap();
+    assert_eq!(camera.count("Stop"), 1);
+}
+
+#[tokio::test]
+async fn skipping_verification_connects() {
+    let camera = MockCamera::start().await;
+    let (url, _proxy) = https_proxy(&camera).await;
+
+    builder(&url)
+        .insecure_skip_verify(true)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(camera.count("GetServices"), 1);
+}